@@ -20,7 +20,8 @@ use primitives::{ed25519, sr25519, Pair, crypto::UncheckedInto};
 use node_primitives::{AccountId, AuraId};
 use node_runtime::{ AuraConfig, SystemConfig,
 	SessionConfig, StakingConfig, StakerStatus, TimestampConfig, BalancesConfig,
-	SudoConfig, ContractsConfig, GrandpaConfig, IndicesConfig, KtonConfig, Permill, Perbill, SessionKeys};
+	SudoConfig, ContractsConfig, GrandpaConfig, IndicesConfig, KtonConfig, TreasuryConfig, Permill, Perbill,
+	SessionKeys};
 pub use node_runtime::GenesisConfig;
 use srml_support::traits::Get;
 // custom
@@ -207,6 +208,7 @@ fn staging_testnet_config_genesis() -> GenesisConfig {
 				.collect(),
 			vesting: vec![],
 			sys_acc: hex!["984d592d15d930ac36e6716407fbed3f7d1e2e62bc11f8429345f8b8b0dfc107"].unchecked_into(),
+			existential_deposit: 0,
 		}),
 		indices: Some(IndicesConfig {
 			ids: endowed_accounts.iter().cloned()
@@ -248,12 +250,12 @@ fn staging_testnet_config_genesis() -> GenesisConfig {
 		timestamp: Some(TimestampConfig {
 			minimum_period: SECS_PER_BLOCK / 2, // due to the nature of aura the slots are 2*period
 		}),
-//		treasury: Some(TreasuryConfig {
-//			proposal_bond: Permill::from_percent(5),
-//			proposal_bond_minimum: 1 * DOLLARS,
-//			spend_period: 1 * DAYS,
-//			burn: Permill::from_percent(50),
-//		}),
+		treasury: Some(TreasuryConfig {
+			proposal_bond: Permill::from_percent(5),
+			proposal_bond_minimum: 1 * DOLLARS,
+			spend_period: 1 * DAYS,
+			burn: Permill::from_percent(50),
+		}),
 		contracts: Some(ContractsConfig {
 			signed_claim_handicap: 2,
 			rent_byte_price: 4,
@@ -433,6 +435,7 @@ pub fn testnet_genesis(
 				.collect(),
 			vesting: vec![],
 			sys_acc: hex!["984d592d15d930ac36e6716407fbed3f7d1e2e62bc11f8429345f8b8b0dfc107"].unchecked_into(),
+			existential_deposit: 0,
 		}),
 		session: Some(SessionConfig {
 			validators: initial_authorities.iter().map(|x| x.1.clone()).collect(),
@@ -471,12 +474,12 @@ pub fn testnet_genesis(
 		timestamp: Some(TimestampConfig {
 			minimum_period: 3,                    // 3*2=6 second block time.
 		}),
-//		treasury: Some(TreasuryConfig {
-//			proposal_bond: Permill::from_percent(5),
-//			proposal_bond_minimum: 1_000_000,
-//			spend_period: 12 * 60 * 24,
-//			burn: Permill::from_percent(50),
-//		}),
+		treasury: Some(TreasuryConfig {
+			proposal_bond: Permill::from_percent(5),
+			proposal_bond_minimum: 1_000_000,
+			spend_period: 12 * 60 * 24,
+			burn: Permill::from_percent(50),
+		}),
 		contracts: Some(contracts_config),
 		sudo: Some(SudoConfig {
 			key: root_key,
@@ -373,6 +373,7 @@ mod tests {
 				],
 				vesting: vec![],
 				sys_acc: ferdie(),
+				existential_deposit: 0,
 			}),
 			session: Some(SessionConfig {
 				validators: vec![AccountKeyring::One.into(), AccountKeyring::Two.into(), three],
@@ -400,6 +401,7 @@ mod tests {
 				invulnerables: vec![alice(), bob(), charlie()],
 			}),
 			timestamp: Some(Default::default()),
+			treasury: Some(Default::default()),
 			contracts: Some(Default::default()),
 			sudo: Some(Default::default()),
 			grandpa: Some(GrandpaConfig {
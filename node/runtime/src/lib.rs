@@ -21,10 +21,10 @@
 #![recursion_limit="256"]
 
 use rstd::prelude::*;
-use support::{construct_runtime, parameter_types};
+use support::{construct_runtime, parameter_types, traits::Get};
 use substrate_primitives::u32_trait::{_1, _2, _3, _4};
 use node_primitives::{
-	AccountId, AccountIndex, Balance, BlockNumber, Hash, Index, Signature, AuraId
+	AccountId, AccountIndex, Balance, BlockNumber, Hash, Index, Signature, AuraId, AuraSignature
 };
 use grandpa::fg_primitives::{self, ScheduledChange};
 use client::{
@@ -100,7 +100,9 @@ impl system::Trait for Runtime {
 }
 
 impl aura::Trait for Runtime {
-	type HandleReport = aura::StakingSlasher<Runtime>;
+	// skipped slots are not punished, the liveness of the validators is checked by their heartbeats
+	type HandleReport = ();
+	type OnBlockAuthored = aura::StakingRewarder<Runtime>;
 	type AuthorityId = AuraId;
 }
 
@@ -138,7 +140,9 @@ impl kton::Trait for Runtime {
 	type Event = Event;
 	type OnMinted = ();
 	type OnRemoval = ();
+	type OnFreeBalanceZero = Staking;
 	type SystemRefund = ();
+	type KtonIssuanceCurve = kton::MonthlyCompoundCurve;
 }
 
 
@@ -176,27 +180,87 @@ parameter_types! {
 	pub const BondingDuration: staking::EraIndex = 24 * 28;
 	// 288 * 365
 	pub const ErasPerEpoch: staking::ErasNums = 105120;
+	// 288 * 7, a week of eras
+	pub const HistoryDepth: staking::EraIndex = 2016;
+	pub const RewardDepositMonths: u32 = 12;
+	// slashes are applied at once until the council can cancel them, only root could otherwise
+	pub const SlashDeferDuration: staking::EraIndex = 0;
+	pub const SlashRewardFraction: Perbill = Perbill::from_percent(10);
+	// locking 10,000 RING for a year mints about 1 KTON
+	pub const RingKtonRatio: u32 = 10_000;
+	pub const MaxNominatorRewardedPerValidator: u32 = 64;
+	// two hours of eras
+	pub const OfflineRecordsDepth: staking::EraIndex = 24;
 }
 
 // customed
 parameter_types! {
 	// decimal 3
 	pub const CAP: Balance = 10_000_000_000_000;
+	pub const StakersRatio: Perbill = Perbill::from_percent(60);
+	// the treasury gets the rest of the era reward, none until the council can approve its spendings
+	// (but the stakers part of an era no validator earned a point in, which nobody can claim)
+	pub const KtonHoldersRatio: Perbill = Perbill::from_percent(40);
+}
+
+pub struct TreasuryAccount;
+impl Get<AccountId> for TreasuryAccount {
+	fn get() -> AccountId {
+		Treasury::account_id()
+	}
+}
+
+pub struct AuraAuthorities;
+impl Get<Vec<AuraId>> for AuraAuthorities {
+	fn get() -> Vec<AuraId> {
+		Aura::authorities()
+	}
+}
+
+pub struct GrandpaAuthorities;
+impl Get<Vec<GrandpaId>> for GrandpaAuthorities {
+	fn get() -> Vec<GrandpaId> {
+		Grandpa::grandpa_authorities().into_iter().map(|(id, _)| id).collect()
+	}
 }
 
 impl staking::Trait for Runtime {
 	type Currency = Kton;
 	type RewardCurrency = Balances;
+	type RingKtonRatio = RingKtonRatio;
 	type CurrencyToVote = CurrencyToVoteHandler;
 	type OnRewardMinted = ();
 	type Event = Event;
 	type Slash = ();
+	type RingSlash = ();
 	type Reward = ();
 	type SessionsPerEra = SessionsPerEra;
+	type SessionLength = Period;
 	type BondingDuration = BondingDuration;
 	// customed
 	type Cap = CAP;
 	type ErasPerEpoch = ErasPerEpoch;
+	type StakersRatio = StakersRatio;
+	type KtonHoldersRatio = KtonHoldersRatio;
+	type TreasuryAccount = TreasuryAccount;
+	type HistoryDepth = HistoryDepth;
+	type RewardDepositMonths = RewardDepositMonths;
+	type SlashDeferDuration = SlashDeferDuration;
+	// TODO: let the council cancel slashes once it is back, and defer them by a day of eras
+	type SlashCancelOrigin = system::EnsureRoot<AccountId>;
+	type SlashRewardFraction = SlashRewardFraction;
+	type MaxNominatorRewardedPerValidator = MaxNominatorRewardedPerValidator;
+	type OfflineRecordsDepth = OfflineRecordsDepth;
+	// validators sign their heartbeats with their aura key
+	type AuthorityId = AuraId;
+	type AuthoritySignature = AuraSignature;
+	type Authorities = AuraAuthorities;
+	// equivocating grandpa votes are checked against the grandpa key
+	type GrandpaAuthorityId = GrandpaId;
+	type GrandpaSignature = substrate_primitives::ed25519::Signature;
+	type GrandpaAuthorities = GrandpaAuthorities;
+	type Call = Call;
+	type UncheckedExtrinsic = UncheckedExtrinsic;
 }
 
 const MINUTES: BlockNumber = 10;
@@ -244,14 +308,16 @@ parameter_types! {
 //	type Event = Event;
 //}
 //
-//impl treasury::Trait for Runtime {
-//	type Currency = Balances;
-//	type ApproveOrigin = council_motions::EnsureMembers<_4, AccountId>;
-//	type RejectOrigin = council_motions::EnsureMembers<_2, AccountId>;
-//	type Event = Event;
-//	type MintedForSpending = ();
-//	type ProposalRejection = ();
-//}
+// TODO: approve and reject through council motions once the council is back, the treasury gets
+// no share of the era reward until then
+impl treasury::Trait for Runtime {
+	type Currency = Balances;
+	type ApproveOrigin = system::EnsureRoot<AccountId>;
+	type RejectOrigin = system::EnsureRoot<AccountId>;
+	type Event = Event;
+	type MintedForSpending = ();
+	type ProposalRejection = ();
+}
 
 impl contracts::Trait for Runtime {
 	type Currency = Balances;
@@ -295,13 +361,13 @@ construct_runtime!(
 //		CouncilSeats: council_seats::{Config<T>},
 		FinalityTracker: finality_tracker::{Module, Call, Inherent},
 		Grandpa: grandpa::{Module, Call, Storage, Config<T>, Event},
-//		Treasury: treasury,
+		Treasury: treasury,
 		Contracts: contracts,
 		Sudo: sudo,
 		// evo module
 //		Ring: ring,
 		Kton: kton,
-		Staking: staking::{default, OfflineWorker},
+		Staking: staking::{default, OfflineWorker, ValidateUnsigned},
 
 	}
 );
@@ -378,6 +444,28 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl staking_runtime_api::StakingApi<Block, AccountId, Balance, Balance> for Runtime {
+		fn current_era() -> staking::EraIndex {
+			Staking::current_era()
+		}
+
+		fn eras_elected(era: staking::EraIndex) -> Vec<AccountId> {
+			Staking::eras_elected(era)
+		}
+
+		fn eras_exposures(era: staking::EraIndex) -> Vec<(AccountId, staking::Exposure<AccountId, Balance>)> {
+			Staking::eras_exposures(era)
+		}
+
+		fn eras_slot_stake(era: staking::EraIndex) -> Option<Balance> {
+			Staking::eras_slot_stake(era)
+		}
+
+		fn eras_total_reward(era: staking::EraIndex) -> Option<Balance> {
+			Staking::eras_total_reward(era)
+		}
+	}
+
 	impl fg_primitives::GrandpaApi<Block> for Runtime {
 		fn grandpa_pending_change(digest: &DigestFor<Block>)
 			-> Option<ScheduledChange<NumberFor<Block>>>
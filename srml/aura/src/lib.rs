@@ -117,10 +117,22 @@ impl HandleReport for () {
 	fn handle_report(_report: AuraReport) { }
 }
 
+/// Something that should be notified of the slot in which a block is authored.
+pub trait OnBlockAuthored {
+	fn on_block_authored(slot: usize);
+}
+
+impl OnBlockAuthored for () {
+	fn on_block_authored(_slot: usize) { }
+}
+
 pub trait Trait: timestamp::Trait {
 	/// The logic for handling reports.
 	type HandleReport: HandleReport;
 
+	/// The logic for handling authored blocks.
+	type OnBlockAuthored: OnBlockAuthored;
+
 	/// The identifier type for an authority.
 	type AuthorityId: Member + Parameter + Default;
 }
@@ -230,28 +242,31 @@ impl<T: Trait> Module<T> {
 			skipped: skipped_slots.saturated_into::<usize>(),
 		})
 	}
+
+	fn on_block_authored<A: OnBlockAuthored>(now: T::Moment, slot_duration: T::Moment) {
+		if slot_duration.is_zero() {
+			return;
+		}
+
+		let slot = now / slot_duration;
+		A::on_block_authored(slot.saturated_into::<usize>());
+	}
 }
 
 impl<T: Trait> OnTimestampSet<T::Moment> for Module<T> {
 	fn on_timestamp_set(moment: T::Moment) {
-		Self::on_timestamp_set::<T::HandleReport>(moment, Self::slot_duration())
+		let slot_duration = Self::slot_duration();
+		Self::on_timestamp_set::<T::HandleReport>(moment.clone(), slot_duration.clone());
+		Self::on_block_authored::<T::OnBlockAuthored>(moment, slot_duration);
 	}
 }
 
-/// A type for performing slashing based on Aura reports.
-pub struct StakingSlasher<T>(::rstd::marker::PhantomData<T>);
+/// A type for giving era reward points to the author of each block.
+pub struct StakingRewarder<T>(::rstd::marker::PhantomData<T>);
 
-impl<T: staking::Trait + Trait> HandleReport for StakingSlasher<T> {
-	fn handle_report(report: AuraReport) {
-		let validators = session::Module::<T>::validators();
-
-		report.punish(
-			validators.len(),
-			|idx, slash_count| {
-				let v = validators[idx].clone();
-				staking::Module::<T>::on_offline_validator(v, slash_count);
-			}
-		);
+impl<T: staking::Trait + Trait> OnBlockAuthored for StakingRewarder<T> {
+	fn on_block_authored(slot: usize) {
+		staking::Module::<T>::reward_slot_author(slot);
 	}
 }
 
@@ -51,6 +51,7 @@ impl timestamp::Trait for Test {
 
 impl Trait for Test {
 	type HandleReport = ();
+	type OnBlockAuthored = ();
 	type AuthorityId = UintAuthorityId;
 }
 
@@ -0,0 +1,30 @@
+//! Runtime API of the staking module, to query the history of the last `HistoryDepth` eras.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use parity_codec::Codec;
+use rstd::prelude::*;
+use staking::{EraIndex, Exposure};
+use substrate_client::decl_runtime_apis;
+
+decl_runtime_apis! {
+	/// The staking history of the eras, e.g. for explorers.
+	///
+	/// Eras out of history have no elected validators, no exposures and no rewards.
+	pub trait StakingApi<AccountId, Balance, RewardBalance> where
+		AccountId: Codec,
+		Balance: Codec,
+		RewardBalance: Codec,
+	{
+		/// The current era.
+		fn current_era() -> EraIndex;
+		/// The validators elected at era.
+		fn eras_elected(era: EraIndex) -> Vec<AccountId>;
+		/// The exposures of the validators elected at era.
+		fn eras_exposures(era: EraIndex) -> Vec<(AccountId, Exposure<AccountId, Balance>)>;
+		/// The slot stake at era.
+		fn eras_slot_stake(era: EraIndex) -> Option<Balance>;
+		/// The total reward of era, once it has ended.
+		fn eras_total_reward(era: EraIndex) -> Option<RewardBalance>;
+	}
+}
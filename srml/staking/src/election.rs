@@ -0,0 +1,220 @@
+//! Election of the validators, computed by the offchain workers.
+//!
+//! Running Phragmén with equalization is too heavy for a block. When the last session of an era
+//! starts, the election window opens and a snapshot of the candidates and nominators is taken.
+//! The offchain workers of the validators run the election on the snapshot and submit the
+//! elected validators with their exposures. Solutions are checked and scored against the
+//! snapshot, the best one elects the validators of the next era. Staking changes are rejected
+//! until the window closes, so that the snapshot stays up to date.
+//!
+//! If no valid solution is submitted, the election is run on-chain, without equalization.
+
+use super::*;
+use rstd::collections::btree_set::BTreeSet;
+
+/// The score of an election solution: the minimal support of the elected validators, the sum of
+/// their supports and the sum of their squared supports.
+pub type ElectionScore = (ExtendedBalance, ExtendedBalance, ExtendedBalance);
+
+/// Whether `this` score is better than `that` one: it has a higher minimal support, then a
+/// higher total support, then a lower variance of the supports.
+pub fn is_score_better(this: ElectionScore, that: ElectionScore) -> bool {
+	(this.0, this.1, that.2) > (that.0, that.1, this.2)
+}
+
+/// Open the election window, taking a snapshot of the candidates and nominators with their
+/// stake.
+pub(crate) fn open_election_window<T: Trait>() {
+	let validators = <Validators<T>>::enumerate()
+		.map(|(v, _)| {
+			let stake = <Module<T>>::slashable_balance_of(&v);
+			(v, stake)
+		})
+		.collect::<Vec<_>>();
+	let nominators = <Nominators<T>>::enumerate()
+		.map(|(n, targets)| {
+			let stake = <Module<T>>::slashable_balance_of(&n);
+			(n, stake, targets)
+		})
+		.collect::<Vec<_>>();
+
+	<SnapshotValidators<T>>::put(validators);
+	<SnapshotNominators<T>>::put(nominators);
+}
+
+/// Staking changes are rejected while the election window is open, as the solutions are computed
+/// on the stake of the snapshot.
+pub(crate) fn ensure_window_closed<T: Trait>() -> result::Result<(), &'static str> {
+	ensure!(<Module<T>>::snapshot_validators().is_none(), "election window is open");
+
+	Ok(())
+}
+
+/// Close the election window, dropping the snapshot and any queued solution.
+pub(crate) fn close_election_window<T: Trait>() {
+	<SnapshotValidators<T>>::kill();
+	<SnapshotNominators<T>>::kill();
+	<QueuedElected<T>>::kill();
+	<QueuedScore<T>>::kill();
+}
+
+/// Run the election on the snapshot, with equalization.
+pub fn compute_solution<T: Trait>() -> Option<Vec<(T::AccountId, Exposure<T::AccountId, BalanceOf<T>>)>> {
+	let validators = <Module<T>>::snapshot_validators()?;
+	let nominators = <Module<T>>::snapshot_nominators()?;
+	let stakes = validators.iter()
+		.map(|(v, stake)| (v.clone(), *stake))
+		.chain(nominators.iter().map(|(n, stake, _)| (n.clone(), *stake)))
+		.collect::<BTreeMap<_, _>>();
+
+	<Module<T>>::do_phragmen(
+		validators.into_iter().map(|(v, _)| {
+			let prefs = <Module<T>>::validators(&v);
+			(v, prefs)
+		}),
+		nominators.into_iter().map(|(n, _, targets)| (n, targets)),
+		|who| stakes.get(who).cloned().unwrap_or_default(),
+		true,
+	)
+}
+
+/// The score of a solution, from the total stake of its elected validators.
+pub fn score_of<T: Trait>(elected: &[(T::AccountId, Exposure<T::AccountId, BalanceOf<T>>)]) -> ElectionScore {
+	elected.iter()
+		.map(|(_, exposure)|
+			<T::CurrencyToVote as Convert<BalanceOf<T>, u64>>::convert(exposure.total) as ExtendedBalance
+		)
+		.fold((ExtendedBalance::max_value(), 0, 0), |score: ElectionScore, support| (
+			score.0.min(support),
+			score.1.saturating_add(support),
+			score.2.saturating_add(support.saturating_mul(support)),
+		))
+}
+
+/// Compute a solution and submit it, signed with the local authority key, unless the election
+/// window is closed or the queued solution is at least as good.
+pub fn send_solution<T: Trait>() -> result::Result<(), &'static str> {
+	if <Module<T>>::snapshot_validators().is_none() {
+		return Ok(());
+	}
+
+	let authority_index = heartbeat::local_authority_index::<T>()?;
+	let era = <Module<T>>::current_era();
+	let elected = compute_solution::<T>().ok_or("not enough candidates")?;
+	if let Some(queued_score) = <Module<T>>::queued_score() {
+		if !is_score_better(score_of::<T>(&elected), queued_score) {
+			return Ok(());
+		}
+	}
+	let signature = heartbeat::sign::<T, _>(heartbeat::SOLUTION_TAG, &(era, &elected))?;
+	heartbeat::submit::<T>(Call::submit_election_solution(era, elected, authority_index, signature))
+}
+
+/// Check a solution against the snapshot, returning its score.
+///
+/// The solution must elect as many validators as the on-chain election would, each backed by
+/// its own stake and the stake of its nominators, no nominator spending more than its stake. Like
+/// in `do_phragmen`, only the `MaxNominatorRewardedPerValidator` nominators with the highest
+/// stake are exposed. It must be better than the queued solution, if any.
+pub fn check_solution<T: Trait>(
+	era: EraIndex,
+	elected: &[(T::AccountId, Exposure<T::AccountId, BalanceOf<T>>)],
+	authority_index: u32,
+	signature: &T::AuthoritySignature,
+) -> result::Result<ElectionScore, &'static str> {
+	ensure!(era == <Module<T>>::current_era(), "solution is not for the current era");
+	let validators = <Module<T>>::snapshot_validators().ok_or("election window is closed")?;
+	let nominators = <Module<T>>::snapshot_nominators().ok_or("election window is closed")?;
+	heartbeat::check_signature::<T, _>(
+		heartbeat::SOLUTION_TAG,
+		authority_index,
+		&(era, elected),
+		signature,
+	)?;
+
+	let expected_count = (<Module<T>>::validator_count() as usize).min(validators.len());
+	ensure!(
+		elected.len() == expected_count &&
+			expected_count >= <Module<T>>::minimum_validator_count().max(1) as usize,
+		"wrong number of elected validators"
+	);
+
+	let validator_stakes = validators.into_iter().collect::<BTreeMap<_, _>>();
+	let nominations = nominators.into_iter()
+		.map(|(n, stake, targets)| (n, (stake, targets)))
+		.collect::<BTreeMap<_, _>>();
+	let mut backers = BTreeMap::<T::AccountId, Vec<(T::AccountId, BalanceOf<T>)>>::new();
+	for (nominator, (stake, targets)) in &nominations {
+		for target in targets.iter().filter(|t| *t != nominator) {
+			backers.entry(target.clone()).or_insert_with(Vec::new).push((nominator.clone(), *stake));
+		}
+	}
+	let max_nominators = T::MaxNominatorRewardedPerValidator::get() as usize;
+
+	let mut winners = BTreeSet::new();
+	let mut spent = BTreeMap::<T::AccountId, BalanceOf<T>>::new();
+	for (validator, exposure) in elected {
+		ensure!(winners.insert(validator.clone()), "validator elected twice");
+		let own = validator_stakes.get(validator).ok_or("elected validator is not a candidate")?;
+		ensure!(exposure.own == *own, "wrong own stake");
+		ensure!(exposure.others.len() <= max_nominators, "too many nominators exposed");
+
+		let mut total = exposure.own;
+		for individual in &exposure.others {
+			let (stake, targets) = nominations.get(&individual.who).ok_or("unknown nominator")?;
+			ensure!(targets.contains(validator), "validator not nominated");
+
+			let spent = spent.entry(individual.who.clone()).or_insert_with(Zero::zero);
+			*spent = spent.saturating_add(individual.value);
+			ensure!(*spent <= *stake, "nominator stake exceeded");
+
+			total = total.saturating_add(individual.value);
+		}
+		ensure!(exposure.total == total, "wrong total stake");
+
+		// All the nominators of an elected validator back it, only the ones with the lowest stake
+		// are left out when there are too many of them.
+		let lowest_exposed = exposure.others.iter()
+			.filter_map(|i| nominations.get(&i.who).map(|(stake, _)| *stake))
+			.min();
+		for (nominator, stake) in backers.get(validator).into_iter().flatten() {
+			if !exposure.others.iter().any(|i| i.who == *nominator) {
+				ensure!(
+					exposure.others.len() == max_nominators &&
+						lowest_exposed.map_or(true, |lowest| *stake <= lowest),
+					"exposed nominators are not the ones with the highest stake"
+				);
+			}
+		}
+	}
+
+	let score = score_of::<T>(elected);
+	if let Some(queued_score) = <Module<T>>::queued_score() {
+		ensure!(is_score_better(score, queued_score), "solution is not better than the queued one");
+	}
+
+	Ok(score)
+}
+
+/// The validity of an election solution transaction. Better solutions have a higher priority.
+pub fn validate_solution<T: Trait>(
+	era: EraIndex,
+	elected: &[(T::AccountId, Exposure<T::AccountId, BalanceOf<T>>)],
+	authority_index: u32,
+	signature: &T::AuthoritySignature,
+) -> TransactionValidity {
+	let score = match check_solution::<T>(era, elected, authority_index, signature) {
+		Ok(score) => score,
+		Err(_) => return TransactionValidity::Invalid(0),
+	};
+
+	TransactionValidity::Valid {
+		priority: score.0.min(u64::max_value() as ExtendedBalance) as u64,
+		requires: vec![],
+		// One solution per era, the best one replaces the others in the pool.
+		provides: vec![(b"election", era).encode()],
+		// The election window closes with the last session of the era.
+		longevity: <Module<T>>::session_longevity(),
+		propagate: true,
+	}
+}
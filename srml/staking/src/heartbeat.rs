@@ -0,0 +1,182 @@
+//! Liveness of the validators.
+//!
+//! Each session, the offchain worker of a validator signs a heartbeat with the validator's
+//! authority key (one of its session keys) and submits it as an unsigned transaction. When the
+//! session ends, the validators without a heartbeat are reported as unresponsive.
+//!
+//! Unresponsive validators are counted offline, like they were for skipped Aura slots and
+//! `report_offline_validator` before: each report adds to their slash count, and they are slashed
+//! and disabled once it exceeds `OfflineSlashGrace` plus their `unstake_threshold`. The slash counts
+//! recorded before the heartbeats still count, and decay the same way.
+//!
+//! Heartbeats are only sent from the block this module is enacted in, by the validators running
+//! the offchain worker with their authority key. The session it is enacted in, at genesis or by a
+//! runtime upgrade, is not checked, so that no validator is reported for the part of the session
+//! it could not send a heartbeat in.
+//!
+//! The authority key signs heartbeats and election solutions, and seals Aura blocks. Heartbeats
+//! and solutions are signed along with a tag, so that no signature can be taken for another.
+
+use super::*;
+use substrate_primitives::offchain::CryptoKey;
+
+/// The tag of heartbeat signatures.
+pub const HEARTBEAT_TAG: &[u8] = b"staking:heartbeat";
+
+/// The tag of election solution signatures.
+pub const SOLUTION_TAG: &[u8] = b"staking:solution";
+
+/// A heartbeat, signed by the authority key of a validator.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Heartbeat<BlockNumber> {
+	/// The block at which the heartbeat was sent.
+	pub block_number: BlockNumber,
+	/// The session the validator is alive in.
+	pub session_index: SessionIndex,
+	/// The index of the validator in the authorities of the session.
+	pub authority_index: u32,
+}
+
+/// The index of the local authority key in the authorities of the current session.
+pub(crate) fn local_authority_index<T: Trait>() -> result::Result<u32, &'static str> {
+	let key = runtime_io::pubkey(CryptoKey::AuthorityKey)
+		.map_err(|_| "no local authority key")?;
+	let authority_id = T::AuthorityId::decode(&mut &key[..])
+		.ok_or("invalid local authority key")?;
+	T::Authorities::get()
+		.iter()
+		.position(|a| *a == authority_id)
+		.map(|i| i as u32)
+		.ok_or("local authority key is not an authority")
+}
+
+/// The payload signed for `data` with `tag`.
+pub fn signed_payload<D: Encode>(tag: &[u8], data: &D) -> Vec<u8> {
+	(tag, data).encode()
+}
+
+/// Sign `data` with the local authority key, along with `tag`.
+pub(crate) fn sign<T: Trait, D: Encode>(
+	tag: &[u8],
+	data: &D,
+) -> result::Result<T::AuthoritySignature, &'static str> {
+	let signature = runtime_io::sign(CryptoKey::AuthorityKey, &signed_payload(tag, data))
+		.map_err(|_| "failed to sign")?;
+	T::AuthoritySignature::decode(&mut &signature[..]).ok_or("invalid signature")
+}
+
+/// Submit a call as an unsigned transaction.
+pub(crate) fn submit<T: Trait>(call: Call<T>) -> result::Result<(), &'static str> {
+	let call: <T as Trait>::Call = call.into();
+	let ex = T::UncheckedExtrinsic::new_unsigned(call)
+		.ok_or("failed to create extrinsic")?;
+	runtime_io::submit_transaction(&ex).map_err(|_| "failed to submit transaction")
+}
+
+/// Check that `data` is signed with `tag` by the authority at `authority_index` in the current
+/// session, returning the authority and its session validator.
+pub(crate) fn check_signature<T: Trait, D: Encode>(
+	tag: &[u8],
+	authority_index: u32,
+	data: &D,
+	signature: &T::AuthoritySignature,
+) -> result::Result<(T::AuthorityId, T::AccountId), &'static str> {
+	let (authority_id, validator) = offences::session_authorities::<T>()?
+		.into_iter()
+		.nth(authority_index as usize)
+		.ok_or("unknown authority")?;
+	ensure!(signature.verify(&signed_payload(tag, data)[..], &authority_id), "bad signature");
+
+	Ok((authority_id, validator))
+}
+
+/// Sign a heartbeat with the local authority key and submit it, unless the local node is not
+/// an authority of the current session or its heartbeat has already been received.
+pub fn send_heartbeat<T: Trait>(block_number: T::BlockNumber) -> result::Result<(), &'static str> {
+	let authority_index = local_authority_index::<T>()?;
+
+	let session_index = <session::Module<T>>::current_index();
+	if <Module<T>>::received_heartbeats(&session_index, &authority_index) {
+		return Ok(());
+	}
+
+	let heartbeat = Heartbeat { block_number, session_index, authority_index };
+	let signature = sign::<T, _>(HEARTBEAT_TAG, &heartbeat)?;
+	submit::<T>(Call::heartbeat(heartbeat, signature))
+}
+
+/// Check a heartbeat against the authorities of the current session, returning the authority
+/// which sent it.
+pub fn check_heartbeat<T: Trait>(
+	heartbeat: &Heartbeat<T::BlockNumber>,
+	signature: &T::AuthoritySignature,
+) -> result::Result<T::AuthorityId, &'static str> {
+	ensure!(
+		heartbeat.session_index == <session::Module<T>>::current_index(),
+		"heartbeat is not for the current session"
+	);
+	ensure!(
+		!<Module<T>>::received_heartbeats(&heartbeat.session_index, &heartbeat.authority_index),
+		"heartbeat already received"
+	);
+
+	check_signature::<T, _>(HEARTBEAT_TAG, heartbeat.authority_index, heartbeat, signature)
+		.map(|(authority_id, _)| authority_id)
+}
+
+/// The validity of a heartbeat transaction.
+pub fn validate_heartbeat<T: Trait>(
+	heartbeat: &Heartbeat<T::BlockNumber>,
+	signature: &T::AuthoritySignature,
+) -> TransactionValidity {
+	if check_heartbeat::<T>(heartbeat, signature).is_err() {
+		return TransactionValidity::Invalid(0);
+	}
+
+	TransactionValidity::Valid {
+		priority: 0,
+		requires: vec![],
+		// One heartbeat per authority and session.
+		provides: vec![(heartbeat.session_index, heartbeat.authority_index).encode()],
+		// Heartbeats are only valid in their session.
+		longevity: <Module<T>>::session_longevity(),
+		propagate: true,
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Report the validators of the ending session which sent no heartbeat, then forget the
+	/// heartbeats of the session.
+	pub(crate) fn check_liveness(session_index: SessionIndex) {
+		let checked = match Self::liveness_checked_from() {
+			Some(first_session) => session_index >= first_session,
+			None => {
+				<LivenessCheckedFrom<T>>::put(session_index + 1);
+				false
+			},
+		};
+
+		// Nobody is reported if the authorities can not be told apart.
+		if let Some(authorities) = offences::session_authorities::<T>().ok().filter(|_| checked) {
+			let offenders = authorities
+				.iter()
+				.enumerate()
+				.filter(|(i, _)| !Self::received_heartbeats(&session_index, &(*i as u32)))
+				.filter_map(|(_, (_, controller))| Self::ledger(controller).map(|l| l.stash))
+				.collect::<Vec<_>>();
+
+			if !offenders.is_empty() {
+				let offence = Offence {
+					kind: OffenceKind::Unresponsiveness,
+					session_index,
+					validator_set_count: authorities.len() as u32,
+					offenders,
+				};
+				let _ = <Self as ReportOffence<_>>::report_offence(vec![], offence);
+			}
+		}
+
+		<ReceivedHeartbeats<T>>::remove_prefix(&session_index);
+	}
+}
@@ -22,12 +22,6 @@
 #[cfg(all(feature = "bench", test))]
 extern crate test;
 
-//#[cfg(any(feature = "bench", test))]
-//mod mock;
-//
-//#[cfg(test)]
-//mod tests;
-
 mod phragmen;
 
 #[cfg(all(feature = "bench", test))]
@@ -36,34 +30,48 @@ extern crate test;
 #[cfg(any(feature = "bench", test))]
 mod mock;
 
+#[cfg(test)]
+mod tests;
+
 #[cfg(all(feature = "bench", test))]
 mod benches;
 
 mod minting;
+mod migration;
+mod slashing;
+mod offences;
+mod heartbeat;
+mod election;
 
 #[cfg(feature = "std")]
 use runtime_io::with_storage;
-use rstd::{prelude::*, result, collections::btree_map::BTreeMap};
+use rstd::{prelude::*, result, convert::TryInto, collections::btree_map::BTreeMap};
 use parity_codec::{HasCompact, Encode, Decode};
 use srml_support::{
-	StorageValue, StorageMap, EnumerableStorageMap, decl_module, decl_event,
-	decl_storage, ensure, traits::{
+	Parameter, StorageValue, StorageMap, EnumerableStorageMap, StorageDoubleMap, decl_module, decl_event,
+	decl_storage, ensure, unsigned::{ValidateUnsigned, TransactionValidity}, traits::{
 		Currency, OnFreeBalanceZero, OnDilution, LockIdentifier, LockableCurrency,
-		WithdrawReasons, OnUnbalanced, Imbalance, Get
+		WithdrawReason, WithdrawReasons, OnUnbalanced, Imbalance, Get
 	}
 };
 use session::{OnSessionEnding, SessionIndex};
 use primitives::Perbill;
+use primitives::transaction_validity::TransactionLongevity;
 use primitives::traits::{
-	Convert, Zero, One, StaticLookup, CheckedSub, CheckedShl, Saturating, Bounded
+	Convert, Zero, One, StaticLookup, CheckedSub, CheckedShl, Saturating, Bounded,
+	SimpleArithmetic, EnsureOrigin, Member, Verify, Extrinsic as ExtrinsicT, Header as HeaderT,
 };
 #[cfg(feature = "std")]
 use primitives::{Serialize, Deserialize};
-use system::ensure_signed;
-use dsupport::traits::SystemCurrency;
+use system::{ensure_none, ensure_root, ensure_signed};
+use dsupport::traits::{DepositRing, LockedBalance, SystemCurrency};
 use phragmen::{elect, ACCURACY, ExtendedBalance};
 
-const RECENT_OFFLINE_COUNT: usize = 32;
+pub use slashing::UnappliedSlash;
+pub use offences::{Equivocation, EquivocationProof, GrandpaVote, Offence, OffenceKind, ReportOffence};
+pub use heartbeat::Heartbeat;
+pub use election::ElectionScore;
+
 const DEFAULT_MINIMUM_VALIDATOR_COUNT: u32 = 10;
 const MAX_NOMINATIONS: usize = 16;
 const MAX_UNSTAKE_THRESHOLD: u32 = 10;
@@ -90,11 +98,16 @@ pub enum StakerStatus<AccountId> {
 #[derive(PartialEq, Eq, Copy, Clone, Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub enum RewardDestination {
+	/// Rewards used to be dropped for this destination. It is migrated to `Staked` and can not be
+	/// chosen anymore.
 	StakedDeprecated,
 	/// Pay into the stash account, not increasing the amount at stake.
 	Stash,
 	/// Pay into the controller account.
 	Controller,
+	/// Pay into the stash account, then lock the reward in a KTON deposit for
+	/// `RewardDepositMonths` and bond the KTON minted, increasing the amount at stake.
+	Staked,
 }
 
 impl Default for RewardDestination {
@@ -106,25 +119,40 @@ impl Default for RewardDestination {
 /// Preference of what happens on a slash event.
 #[derive(PartialEq, Eq, Clone, Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Debug))]
-pub struct ValidatorPrefs<Balance: HasCompact> {
+pub struct ValidatorPrefs {
 	/// Validator should ensure this many more slashes than is necessary before being unstaked.
 	#[codec(compact)]
 	pub unstake_threshold: u32,
-	/// Reward that validator takes up-front; only the rest is split between themselves and
-	/// nominators.
-	#[codec(compact)]
-	pub validator_payment: Balance,
+	/// Part of the reward that validator takes up-front; only the rest is split between
+	/// themselves and nominators.
+	///
+	/// Once validating, a change only takes effect at the next era boundary, see
+	/// `PendingCommission`.
+	pub commission: Perbill,
+	/// Whether the validator refuses new nominations. The ones it already has are kept.
+	pub blocked: bool,
 }
 
-impl<B: Default + HasCompact + Copy> Default for ValidatorPrefs<B> {
+impl Default for ValidatorPrefs {
 	fn default() -> Self {
 		ValidatorPrefs {
 			unstake_threshold: 3,
-			validator_payment: Default::default(),
+			commission: Perbill::zero(),
+			blocked: false,
 		}
 	}
 }
 
+/// A balance of one of the two currencies which can be bonded.
+#[derive(PartialEq, Eq, Copy, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum StakingBalance<RingBalance, KtonBalance> {
+	/// Some RING.
+	Ring(RingBalance),
+	/// Some KTON.
+	Kton(KtonBalance),
+}
+
 /// Just a Balance/BlockNumber tuple to encode when a chunk of funds will be unlocked.
 #[derive(PartialEq, Eq, Clone, Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Debug))]
@@ -138,44 +166,190 @@ pub struct UnlockChunk<Balance: HasCompact> {
 }
 
 /// The ledger of a (bonded) stash.
-#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+///
+/// RING and KTON are bonded separately, each with its own unlocking chunks.
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Default)]
 #[cfg_attr(feature = "std", derive(Debug))]
-pub struct StakingLedger<AccountId, Balance: HasCompact> {
+pub struct StakingLedger<AccountId, RingBalance: HasCompact, KtonBalance: HasCompact> {
 	/// The stash account whose balance is actually locked and at stake.
 	pub stash: AccountId,
-	/// The total amount of the stash's balance that we are currently accounting for.
+	/// The total amount of the stash's KTON that we are currently accounting for.
 	/// It's just `active` plus all the `unlocking` balances.
 	#[codec(compact)]
-	pub total: Balance,
-	/// The total amount of the stash's balance that will be at stake in any forthcoming
+	pub total: KtonBalance,
+	/// The total amount of the stash's KTON that will be at stake in any forthcoming
 	/// rounds.
 	#[codec(compact)]
-	pub active: Balance,
-	/// Any balance that is becoming free, which may eventually be transferred out
+	pub active: KtonBalance,
+	/// Any KTON that is becoming free, which may eventually be transferred out
 	/// of the stash (assuming it doesn't get slashed first).
-	pub unlocking: Vec<UnlockChunk<Balance>>,
+	pub unlocking: Vec<UnlockChunk<KtonBalance>>,
+	/// The total amount of the stash's RING that we are currently accounting for.
+	/// It's just `active_ring` plus all the `ring_unlocking` balances.
+	#[codec(compact)]
+	pub total_ring: RingBalance,
+	/// The total amount of the stash's RING that will be at stake in any forthcoming
+	/// rounds.
+	#[codec(compact)]
+	pub active_ring: RingBalance,
+	/// Any RING that is becoming free, which may eventually be transferred out
+	/// of the stash (assuming it doesn't get slashed first).
+	pub ring_unlocking: Vec<UnlockChunk<RingBalance>>,
 }
 
 impl<
 	AccountId,
-	Balance: HasCompact + Copy + Saturating,
-> StakingLedger<AccountId, Balance> {
-	/// Remove entries from `unlocking` that are sufficiently old and reduce the
-	/// total by the sum of their balances.
-	fn consolidate_unlocked(self, current_era: EraIndex) -> Self {
-		let mut total = self.total;
-		let unlocking = self.unlocking.into_iter()
-			.filter(|chunk| if chunk.era > current_era {
-				true
-			} else {
-				total = total.saturating_sub(chunk.value);
-				false
-			})
-			.collect();
-		Self { total, active: self.active, stash: self.stash, unlocking }
+	RingBalance: HasCompact + SimpleArithmetic + Copy,
+	KtonBalance: HasCompact + SimpleArithmetic + Copy,
+> StakingLedger<AccountId, RingBalance, KtonBalance> {
+	/// Remove entries from `unlocking` and `ring_unlocking` that are sufficiently old and reduce
+	/// the totals by the sum of their balances.
+	fn consolidate_unlocked(mut self, current_era: EraIndex) -> Self {
+		consolidate_chunks(&mut self.total, &mut self.unlocking, current_era);
+		consolidate_chunks(&mut self.total_ring, &mut self.ring_unlocking, current_era);
+		self
+	}
+
+	/// Re-bond funds that were scheduled for unlocking, starting with the most recent chunks.
+	/// At most `value` is re-bonded, a chunk is split if needed.
+	fn rebond(mut self, value: StakingBalance<RingBalance, KtonBalance>) -> Self {
+		match value {
+			StakingBalance::Ring(r) => rebond_chunks(&mut self.active_ring, &mut self.ring_unlocking, r),
+			StakingBalance::Kton(k) => rebond_chunks(&mut self.active, &mut self.unlocking, k),
+		}
+		self
+	}
+
+	/// Slash the validator for the given amounts of RING and KTON. This can grow the value of
+	/// a slash in the case that the validator has less than the minimum balance of active
+	/// funds. Returns the amounts of RING and KTON actually slashed.
+	///
+	/// Slashes from `active` funds first, and then `unlocking`, starting with the chunks that are
+	/// closest to unlocking, so funds being unbonded can not escape a slash.
+	fn slash(
+		&mut self,
+		ring_value: RingBalance,
+		kton_value: KtonBalance,
+		ring_minimum_balance: RingBalance,
+		kton_minimum_balance: KtonBalance,
+	) -> (RingBalance, KtonBalance) {
+		let ring_slashed = slash_chunks(
+			&mut self.total_ring,
+			&mut self.active_ring,
+			&mut self.ring_unlocking,
+			ring_value,
+			ring_minimum_balance,
+		);
+		let kton_slashed = slash_chunks(
+			&mut self.total,
+			&mut self.active,
+			&mut self.unlocking,
+			kton_value,
+			kton_minimum_balance,
+		);
+		(ring_slashed, kton_slashed)
+	}
+}
+
+/// Remove the chunks unlocked at `current_era` and reduce `total` by the sum of their balances.
+fn consolidate_chunks<Balance: HasCompact + SimpleArithmetic + Copy>(
+	total: &mut Balance,
+	unlocking: &mut Vec<UnlockChunk<Balance>>,
+	current_era: EraIndex,
+) {
+	let mut unlocked = Zero::zero();
+	unlocking.retain(|chunk| if chunk.era > current_era {
+		true
+	} else {
+		unlocked += chunk.value;
+		false
+	});
+	*total = total.saturating_sub(unlocked);
+}
+
+/// Schedule `value` of `active` for unlocking at `era`, not leaving a dust balance active.
+fn unbond_chunk<Balance: HasCompact + SimpleArithmetic + Copy>(
+	active: &mut Balance,
+	unlocking: &mut Vec<UnlockChunk<Balance>>,
+	value: Balance,
+	minimum_balance: Balance,
+	era: EraIndex,
+) {
+	let mut value = value.min(*active);
+
+	if !value.is_zero() {
+		*active -= value;
+
+		// Avoid there being a dust balance left in the staking system.
+		if *active < minimum_balance {
+			value += *active;
+			*active = Zero::zero();
+		}
+
+		unlocking.push(UnlockChunk { value, era });
+	}
+}
+
+/// Move at most `value` from the most recent unlocking chunks back to `active`.
+fn rebond_chunks<Balance: HasCompact + SimpleArithmetic + Copy>(
+	active: &mut Balance,
+	unlocking: &mut Vec<UnlockChunk<Balance>>,
+	value: Balance,
+) {
+	let mut unlocking_balance: Balance = Zero::zero();
+
+	while let Some(last) = unlocking.last_mut() {
+		if unlocking_balance + last.value <= value {
+			unlocking_balance += last.value;
+			*active += last.value;
+			unlocking.pop();
+		} else {
+			let diff = value - unlocking_balance;
+
+			unlocking_balance += diff;
+			*active += diff;
+			last.value -= diff;
+		}
+
+		if unlocking_balance >= value {
+			break
+		}
 	}
 }
 
+/// Slash `value` from `active` and then from `unlocking`, returning the amount actually slashed.
+fn slash_chunks<Balance: HasCompact + SimpleArithmetic + Copy>(
+	total: &mut Balance,
+	active: &mut Balance,
+	unlocking: &mut Vec<UnlockChunk<Balance>>,
+	mut value: Balance,
+	minimum_balance: Balance,
+) -> Balance {
+	let mut slashed = Zero::zero();
+	let targets = rstd::iter::once(active)
+		.chain(unlocking.iter_mut().map(|chunk| &mut chunk.value));
+	for target in targets {
+		if value.is_zero() {
+			break;
+		}
+
+		let mut slash_from_target = value.min(*target);
+		*target -= slash_from_target;
+		value -= slash_from_target;
+
+		// Don't leave a dust balance in the staking system.
+		if *target < minimum_balance {
+			slash_from_target += *target;
+			*target = Zero::zero();
+		}
+		slashed += slash_from_target;
+	}
+
+	unlocking.retain(|chunk| !chunk.value.is_zero());
+	*total = total.saturating_sub(slashed);
+	slashed
+}
+
 /// The amount of exposure (to slashing) than an individual nominator has.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Debug))]
@@ -188,6 +362,8 @@ pub struct IndividualExposure<AccountId, Balance: HasCompact> {
 }
 
 /// A snapshot of the stake backing a single validator in the system.
+///
+/// The stake is the voting power of the bonded RING and KTON, see `slashable_balance_of`.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Encode, Decode, Default)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub struct Exposure<AccountId, Balance: HasCompact> {
@@ -201,6 +377,38 @@ pub struct Exposure<AccountId, Balance: HasCompact> {
 	pub others: Vec<IndividualExposure<AccountId, Balance>>,
 }
 
+/// Reward points of the elected validators in an era.
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct EraRewardPoints<AccountId> {
+	/// Total number of points. Equals the sum of reward points for each validator.
+	pub total: u32,
+	/// The reward points earned by a given validator stash.
+	pub individual: Vec<(AccountId, u32)>,
+}
+
+impl<AccountId: PartialEq> EraRewardPoints<AccountId> {
+	/// Add the reward points to the given validator stash.
+	fn add_points(&mut self, who: AccountId, points: u32) {
+		if let Some(new_total) = self.total.checked_add(points) {
+			self.total = new_total;
+			match self.individual.iter_mut().find(|(stash, _)| *stash == who) {
+				// Addition is less than total
+				Some((_, p)) => *p += points,
+				None => self.individual.push((who, points)),
+			}
+		}
+	}
+
+	/// The reward points earned by the given validator stash.
+	pub fn points_of(&self, who: &AccountId) -> u32 {
+		self.individual.iter()
+			.find(|(stash, _)| stash == who)
+			.map(|(_, points)| *points)
+			.unwrap_or(0)
+	}
+}
+
 // for kton
 type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 // for ring
@@ -209,6 +417,17 @@ type RewardBalanceOf<T> = <<T as Trait>::RewardCurrency as Currency<<T as system
 type PositiveImbalanceOf<T> = <<T as Trait>::RewardCurrency as Currency<<T as system::Trait>::AccountId>>::PositiveImbalance;
 // imbalance of kton - for slashing
 type NegativeImbalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::NegativeImbalance;
+// imbalance of ring - for slashing
+type RingNegativeImbalanceOf<T> = <<T as Trait>::RewardCurrency as Currency<<T as system::Trait>::AccountId>>::NegativeImbalance;
+// ledger of both currencies
+type StakingLedgerOf<T> = StakingLedger<<T as system::Trait>::AccountId, RewardBalanceOf<T>, BalanceOf<T>>;
+// equivocation of aura blocks or grandpa votes
+type EquivocationProofOf<T> = EquivocationProof<
+	<T as system::Trait>::Header,
+	<T as system::Trait>::Hash,
+	<T as system::Trait>::BlockNumber,
+	<T as Trait>::GrandpaSignature,
+>;
 
 
 type RawAssignment<T> = (<T as system::Trait>::AccountId, ExtendedBalance);
@@ -218,13 +437,18 @@ type ExpoMap<T> = BTreeMap<
 	Exposure<<T as system::Trait>::AccountId, BalanceOf<T>>
 >;
 
-pub trait Trait: system::Trait + session::Trait {
+pub trait Trait: system::Trait + session::Trait + timestamp::Trait {
 	/// The staking balance.
 	type Currency: LockableCurrency<Self::AccountId, Moment=Self::BlockNumber> +
-		SystemCurrency<Self::AccountId, <Self::RewardCurrency as Currency<Self::AccountId>>::Balance>;
+		SystemCurrency<Self::AccountId, <Self::RewardCurrency as Currency<Self::AccountId>>::Balance> +
+		DepositRing<Self::AccountId, <Self::RewardCurrency as Currency<Self::AccountId>>::Balance>;
 
 	// Customed: for ring
-	type RewardCurrency: Currency<Self::AccountId>;
+	type RewardCurrency: LockableCurrency<Self::AccountId, Moment=Self::Moment> + LockedBalance<Self::AccountId>;
+
+	/// The RING:KTON ratio of the voting power, i.e. the number of bonded RING weighing as much
+	/// as one bonded KTON.
+	type RingKtonRatio: Get<u32>;
 
 	/// Convert a balance into a number used for election calculation.
 	/// This must fit into a `u64` but is allowed to be sensibly lossy.
@@ -242,18 +466,92 @@ pub trait Trait: system::Trait + session::Trait {
 	/// Handler for the unbalanced reduction when slashing a staker.
 	type Slash: OnUnbalanced<NegativeImbalanceOf<Self>>;
 
+	/// Handler for the unbalanced reduction of RING when slashing a staker.
+	type RingSlash: OnUnbalanced<RingNegativeImbalanceOf<Self>>;
+
 	/// Handler for the unbalanced increment when rewarding a staker.
 	type Reward: OnUnbalanced<PositiveImbalanceOf<Self>>;
 
 	/// Number of sessions per era.
 	type SessionsPerEra: Get<SessionIndex>;
 
+	/// Number of blocks per session, the period the session module rotates sessions with.
+	///
+	/// Heartbeats and election solutions are valid in the transaction pool until their session
+	/// ends.
+	type SessionLength: Get<Self::BlockNumber>;
+
 	/// Number of eras that staked funds must remain bonded for.
 	type BondingDuration: Get<EraIndex>;
 
 	// customed
 	type Cap: Get<<Self::RewardCurrency as Currency<Self::AccountId>>::Balance>;
 	type ErasPerEpoch: Get<ErasNums>;
+
+	/// Part of the era reward paid to the validators and their nominators.
+	type StakersRatio: Get<Perbill>;
+
+	/// Part of the era reward shared by the KTON holders.
+	type KtonHoldersRatio: Get<Perbill>;
+
+	/// The account receiving the rest of the era reward.
+	type TreasuryAccount: Get<Self::AccountId>;
+
+	/// Number of eras to keep in history.
+	///
+	/// Rewards of older eras can no longer be claimed with `payout_stakers`.
+	type HistoryDepth: Get<EraIndex>;
+
+	/// Number of months the rewards of `RewardDestination::Staked` are locked for in KTON
+	/// deposits.
+	type RewardDepositMonths: Get<u32>;
+
+	/// Number of eras that slashes are deferred by, after computation. This should be less than
+	/// the bonding duration. Set to 0 if slashes should be applied immediately, without
+	/// opportunity for intervention.
+	type SlashDeferDuration: Get<EraIndex>;
+
+	/// The origin which can cancel a deferred slash. Root can always do this.
+	type SlashCancelOrigin: EnsureOrigin<Self::Origin>;
+
+	/// Part of a slash given to the reporters of the offence.
+	type SlashRewardFraction: Get<Perbill>;
+
+	/// The maximum number of nominators exposed by each validator, the ones with the highest
+	/// stake. The others neither get rewards nor slashes from it.
+	///
+	/// This bounds the weight of paying out and slashing a validator.
+	type MaxNominatorRewardedPerValidator: Get<u32>;
+
+	/// Number of eras the offline reports of a validator are kept for. Each of them counts one
+	/// less towards its slash count for every era that has passed since.
+	type OfflineRecordsDepth: Get<EraIndex>;
+
+	/// The key validators sign their heartbeats with, one of their session keys.
+	type AuthorityId: Member + Parameter + Default;
+
+	/// A signature by an `AuthorityId`, of a heartbeat, an election solution or an Aura block.
+	type AuthoritySignature: Verify<Signer = Self::AuthorityId> + Member + Parameter;
+
+	/// The authorities of the current session, in the order of the session validators. They are
+	/// ignored if they are not as many as the session validators.
+	type Authorities: Get<Vec<Self::AuthorityId>>;
+
+	/// The key validators sign their GRANDPA votes with, one of their session keys.
+	type GrandpaAuthorityId: Member + Parameter;
+
+	/// A signature of a GRANDPA vote by a `GrandpaAuthorityId`.
+	type GrandpaSignature: Verify<Signer = Self::GrandpaAuthorityId> + Member + Parameter;
+
+	/// The GRANDPA authorities of the current session, in the order of the session validators.
+	/// They are ignored if they are not as many as the session validators.
+	type GrandpaAuthorities: Get<Vec<Self::GrandpaAuthorityId>>;
+
+	/// The overarching call type, used to submit heartbeats.
+	type Call: From<Call<Self>>;
+
+	/// The extrinsic type heartbeats are submitted with by the offchain worker.
+	type UncheckedExtrinsic: ExtrinsicT<Call = <Self as Trait>::Call> + Encode + Decode;
 }
 
 decl_storage! {
@@ -279,14 +577,21 @@ decl_storage! {
 		/// Map from all locked "stash" accounts to the controller account.
 		pub Bonded get(bonded): map T::AccountId => Option<T::AccountId>;
 		/// Map from all (unlocked) "controller" accounts to the info regarding the staking.
-		pub Ledger get(ledger):
-			map T::AccountId => Option<StakingLedger<T::AccountId, BalanceOf<T>>>;
+		///
+		/// Read with `ledger`, some ledgers may still be in their layout before version 2.
+		pub Ledger: map T::AccountId => Option<StakingLedgerOf<T>>;
 
 		/// Where the reward payment should be made. Keyed by stash.
 		pub Payee get(payee): map T::AccountId => RewardDestination;
 
 		/// The map from (wannabe) validator stash key to the preferences of that validator.
-		pub Validators get(validators): linked_map T::AccountId => ValidatorPrefs<RewardBalanceOf<T>>;
+		pub Validators get(validators): linked_map T::AccountId => ValidatorPrefs;
+
+		/// The commission a validator has asked for, which it gets at the next era boundary.
+		///
+		/// Until then, the commission in `Validators` is the one of the era the validator is
+		/// elected in, so that its nominators can react to a hike before it applies.
+		pub PendingCommission get(pending_commission): linked_map T::AccountId => Perbill;
 
 		/// The map from nominator stash key to the set of stash keys of all validators to nominate.
 		pub Nominators get(nominators): linked_map T::AccountId => Vec<T::AccountId>;
@@ -310,6 +615,52 @@ decl_storage! {
 		/// and increased for every successfully finished session.
 		pub CurrentEraTotalReward get(current_era_total_reward) config(): RewardBalanceOf<T>;
 
+		/// The validators elected at era.
+		///
+		/// Only the last `HistoryDepth` eras are kept.
+		pub ErasElected get(eras_elected): map EraIndex => Vec<T::AccountId>;
+
+		/// The amount of stake backing the least staked validator slot at era.
+		///
+		/// Only the last `HistoryDepth` eras are kept.
+		pub ErasSlotStake get(eras_slot_stake): map EraIndex => Option<BalanceOf<T>>;
+
+		/// The total reward of an era, before it is split between the stakers, the KTON holders
+		/// and the treasury. Set once the era has ended.
+		///
+		/// Only the last `HistoryDepth` eras are kept.
+		pub ErasTotalReward get(eras_total_reward): map EraIndex => Option<RewardBalanceOf<T>>;
+
+		/// Exposure of validator at era.
+		///
+		/// This is keyed first by the era index to allow bulk deletion and then the stash account.
+		/// Only the last `HistoryDepth` eras are kept.
+		pub ErasStakers get(eras_stakers):
+			double_map EraIndex, twox_128(T::AccountId) => Exposure<T::AccountId, BalanceOf<T>>;
+
+		/// Preferences of a validator at era, used to pay out its rewards.
+		///
+		/// Only the last `HistoryDepth` eras are kept.
+		pub ErasValidatorPrefs get(eras_validator_prefs):
+			double_map EraIndex, twox_128(T::AccountId) => ValidatorPrefs;
+
+		/// The total reward to be shared by the stakers of an era, pro-rata to the reward points
+		/// of their validators.
+		///
+		/// Only the last `HistoryDepth` eras are kept.
+		pub ErasValidatorReward get(eras_validator_reward): map EraIndex => Option<RewardBalanceOf<T>>;
+
+		/// Rewards points of the validators for an era. The era reward is split between them
+		/// pro-rata to their points.
+		///
+		/// Only the last `HistoryDepth` eras are kept.
+		pub ErasRewardPoints get(eras_reward_points): map EraIndex => EraRewardPoints<T::AccountId>;
+
+		/// Whether the reward of a validator stash (and its nominators) at era has been paid out.
+		///
+		/// Only the last `HistoryDepth` eras are kept.
+		pub ErasRewardClaimed get(eras_reward_claimed): double_map EraIndex, twox_128(T::AccountId) => bool;
+
 		/// The amount of balance actively at stake for each validator slot, currently.
 		///
 		/// This is used to derive rewards and punishments.
@@ -317,19 +668,83 @@ decl_storage! {
 			config.stakers.iter().map(|&(_, _, value, _)| value).min().unwrap_or_default()
 		}): BalanceOf<T>;
 
-		/// The number of times a given validator has been reported offline. This gets decremented
-		/// by one each era that passes.
-		pub SlashCount get(slash_count): map T::AccountId => u32;
-
-		/// Most recent `RECENT_OFFLINE_COUNT` instances. (Who it was, when it was reported, how
-		/// many instances they were offline for).
-		pub RecentlyOffline get(recently_offline): Vec<(T::AccountId, T::BlockNumber, u32)>;
+		/// The number of times a given validator has been reported offline in an era.
+		///
+		/// Only the last `OfflineRecordsDepth` eras are kept, see `slash_count`.
+		pub OfflineRecords get(offline_records): double_map EraIndex, twox_128(T::AccountId) => u32;
 
 		/// True if the next session change will be a new era regardless of index.
 		pub ForceNewEra get(forcing_new_era): bool;
 
+		/// The block the current session started at.
+		pub SessionStart get(session_start): T::BlockNumber;
+
+		/// True if the last election failed to elect `MinimumValidatorCount` validators. The
+		/// previous set is kept and no era reward is minted until a new set is elected, or forced
+		/// with `force_set_validators`.
+		pub EmergencyMode get(emergency_mode): bool;
+
 		// customed
 		pub EpochIndex get(epoch_index) config(): T::BlockNumber;
+
+		/// All unapplied slashes that are queued for later, keyed by the era they are applied at.
+		pub UnappliedSlashes get(unapplied_slashes):
+			map EraIndex => Vec<UnappliedSlash<T::AccountId, BalanceOf<T>>>;
+
+		/// The slashing spans of a staker, see `slashing::SlashingSpans`.
+		pub SlashingSpans get(slashing_spans): map T::AccountId => slashing::SlashingSpans;
+
+		/// The highest slash of a staker in one of its slashing spans, the cancelled slashes
+		/// excluded.
+		pub SpanSlash get(span_slash):
+			double_map T::AccountId, twox_128(slashing::SpanIndex) => BalanceOf<T>;
+
+		/// The session index at which an era started.
+		///
+		/// Only the last `HistoryDepth` eras are kept.
+		pub ErasStartSessionIndex get(eras_start_session_index): map EraIndex => Option<SessionIndex>;
+
+		/// The offenders already reported for a kind of offence in a session, keyed by the era of
+		/// the session.
+		///
+		/// Only the last `HistoryDepth` eras are kept.
+		pub OffenceReports get(offence_reports):
+			double_map EraIndex, blake2_256((OffenceKind, SessionIndex)) => Vec<T::AccountId>;
+
+		/// The equivocations already reported.
+		///
+		/// They are kept forever, so that an equivocation can not be reported again in a later
+		/// session.
+		pub ReportedEquivocations get(reported_equivocations): map Equivocation<T::AccountId> => bool;
+
+		/// The first session the liveness of the validators is checked in, the one after the
+		/// heartbeats were first sent in.
+		pub LivenessCheckedFrom get(liveness_checked_from): Option<SessionIndex>;
+
+		/// The heartbeats received in a session, keyed by the index of the authority.
+		///
+		/// Only the heartbeats of the current session are kept.
+		pub ReceivedHeartbeats get(received_heartbeats):
+			double_map SessionIndex, twox_128(u32) => bool;
+
+		/// The validator candidates with their stake, taken when the election window opens.
+		pub SnapshotValidators get(snapshot_validators): Option<Vec<(T::AccountId, BalanceOf<T>)>>;
+
+		/// The nominators with their stake and their targets, taken when the election window
+		/// opens.
+		pub SnapshotNominators get(snapshot_nominators):
+			Option<Vec<(T::AccountId, BalanceOf<T>, Vec<T::AccountId>)>>;
+
+		/// The best election solution submitted in the election window: the elected stashes with
+		/// their exposures.
+		pub QueuedElected get(queued_elected):
+			Option<Vec<(T::AccountId, Exposure<T::AccountId, BalanceOf<T>>)>>;
+
+		/// The score of `QueuedElected`.
+		pub QueuedScore get(queued_score): Option<ElectionScore>;
+
+		/// The version of the storage layout, see `migration`.
+		StorageVersion build(|_| migration::CURRENT_VERSION): u32;
 	}
 	add_extra_genesis {
 		config(stakers):
@@ -345,7 +760,7 @@ decl_storage! {
 					let _ = <Module<T>>::bond(
 						T::Origin::from(Some(stash.clone()).into()),
 						T::Lookup::unlookup(controller.clone()),
-						balance,
+						StakingBalance::Kton(balance),
 						RewardDestination::Stash
 					);
 					let _ = match status {
@@ -375,14 +790,40 @@ decl_event!(
 	pub enum Event<T> where
 		RewardBalance = RewardBalanceOf<T>,
 		Balance = BalanceOf<T>,
+		AuthorityId = <T as Trait>::AuthorityId,
 		<T as system::Trait>::AccountId {
-		/// All validators have been rewarded by the given balance.
-		Reward(RewardBalance),
+		/// The era has ended and the given balance can be claimed by its stakers through
+		/// `payout_stakers`.
+		Reward(EraIndex, RewardBalance),
+		/// One validator (and its nominators) has been paid out for an era.
+		Payout(AccountId, EraIndex, RewardBalance),
+		/// The reward of one staker could not be locked in a deposit, it was paid into its stash
+		/// and left free.
+		RewardNotStaked(AccountId, RewardBalance),
+		/// The treasury has been given its part of the era reward.
+		TreasuryReward(RewardBalance),
 		/// One validator (and its nominators) has been given an offline-warning (it is still
 		/// within its grace). The accrued number of slashes is recorded, too.
 		OfflineWarning(AccountId, u32),
-		/// One validator (and its nominators) has been slashed by the given amount.
+		/// One validator (and its nominators) has been reported offline too many times, a slash
+		/// of up to the given amount is to be applied, see `Slash` and `SlashDeferred`.
 		OfflineSlash(AccountId, Balance),
+		/// The slash of one validator (and its nominators) has been deferred to the given era, it
+		/// can be cancelled until then.
+		SlashDeferred(AccountId, EraIndex),
+		/// One staker has been slashed by the given amounts of RING and KTON, once its slash was
+		/// applied.
+		Slash(AccountId, RewardBalance, Balance),
+		/// One validator has been reported for an offence in the given session.
+		Offence(OffenceKind, SessionIndex, AccountId),
+		/// A heartbeat has been received from the given authority in the given session.
+		HeartbeatReceived(SessionIndex, AuthorityId),
+		/// An election solution for the given era has been queued, with the given minimal
+		/// support.
+		ElectionSolutionQueued(EraIndex, u128),
+		/// The election for the given era failed, the previous validators are kept and the
+		/// emergency mode is on.
+		ElectionFailed(EraIndex),
 	}
 );
 
@@ -390,12 +831,22 @@ decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		fn deposit_event<T>() = default;
 
+		fn on_initialize() {
+			migration::on_runtime_upgrade::<T>();
+		}
+
+		fn offchain_worker(now: T::BlockNumber) {
+			let _ = heartbeat::send_heartbeat::<T>(now);
+			let _ = election::send_solution::<T>();
+		}
+
 		fn bond(origin,
 			controller: <T::Lookup as StaticLookup>::Source,
-			#[compact] value: BalanceOf<T>,
+			value: StakingBalance<RewardBalanceOf<T>, BalanceOf<T>>,
 			payee: RewardDestination
 		) {
 			let stash = ensure_signed(origin)?;
+			election::ensure_window_closed::<T>()?;
 
 			if <Bonded<T>>::exists(&stash) {
 				return Err("stash already bonded")
@@ -407,71 +858,90 @@ decl_module! {
 				return Err("controller already paired")
 			}
 
+			ensure!(payee != RewardDestination::StakedDeprecated, "reward destination is deprecated");
+
 			// You're auto-bonded forever, here. We might improve this by only bonding when
 			// you actually validate/nominate and remove once you unbond __everything__.
 			<Bonded<T>>::insert(&stash, controller.clone());
 			<Payee<T>>::insert(&stash, payee);
 
-			let stash_balance = T::Currency::free_balance(&stash);
-			let value = value.min(stash_balance);
-			let item = StakingLedger { stash, total: value, active: value, unlocking: vec![] };
-			Self::update_ledger(&controller, &item);
+			let ledger = StakingLedger { stash, ..Default::default() };
+			Self::bond_helper(&controller, ledger, value);
 		}
 
 
-		fn bond_extra(origin, #[compact] max_additional: BalanceOf<T>) {
+		fn bond_extra(origin, max_additional: StakingBalance<RewardBalanceOf<T>, BalanceOf<T>>) {
 			let stash = ensure_signed(origin)?;
+			election::ensure_window_closed::<T>()?;
 
 			let controller = Self::bonded(&stash).ok_or("not a stash")?;
-			let mut ledger = Self::ledger(&controller).ok_or("not a controller")?;
-
-			let stash_balance = T::Currency::free_balance(&stash);
+			let ledger = Self::ledger(&controller).ok_or("not a controller")?;
 
-			if let Some(extra) = stash_balance.checked_sub(&ledger.total) {
-				let extra = extra.min(max_additional);
-				ledger.total += extra;
-				ledger.active += extra;
-				Self::update_ledger(&controller, &ledger);
-			}
+			Self::bond_helper(&controller, ledger, max_additional);
 		}
 
 
-		fn unbond(origin, #[compact] value: BalanceOf<T>) {
+		fn unbond(origin, value: StakingBalance<RewardBalanceOf<T>, BalanceOf<T>>) {
 			let controller = ensure_signed(origin)?;
+			election::ensure_window_closed::<T>()?;
 			let mut ledger = Self::ledger(&controller).ok_or("not a controller")?;
-			ensure!(
-				ledger.unlocking.len() < MAX_UNLOCKING_CHUNKS,
-				"can not schedule more unlock chunks"
-			);
+			let era = Self::current_era() + T::BondingDuration::get();
 
-			let mut value = value.min(ledger.active);
+			match value {
+				StakingBalance::Ring(r) => {
+					ensure!(
+						ledger.ring_unlocking.len() < MAX_UNLOCKING_CHUNKS,
+						"can not schedule more unlock chunks"
+					);
+					let minimum_balance = T::RewardCurrency::minimum_balance();
+					unbond_chunk(&mut ledger.active_ring, &mut ledger.ring_unlocking, r, minimum_balance, era);
+				},
+				StakingBalance::Kton(k) => {
+					ensure!(
+						ledger.unlocking.len() < MAX_UNLOCKING_CHUNKS,
+						"can not schedule more unlock chunks"
+					);
+					let minimum_balance = T::Currency::minimum_balance();
+					unbond_chunk(&mut ledger.active, &mut ledger.unlocking, k, minimum_balance, era);
+				},
+			}
 
-			if !value.is_zero() {
-				ledger.active -= value;
+			Self::update_ledger(&controller, &ledger);
+		}
 
-				// Avoid there being a dust balance left in the staking system.
-				if ledger.active < T::Currency::minimum_balance() {
-					value += ledger.active;
-					ledger.active = Zero::zero();
-				}
 
-				let era = Self::current_era() + T::BondingDuration::get();
-				ledger.unlocking.push(UnlockChunk { value, era });
-				Self::update_ledger(&controller, &ledger);
-			}
+		/// Rebond a portion of the stash scheduled to be unlocked.
+		///
+		/// The most recent unlocking chunks are rebonded first.
+		///
+		/// The dispatch origin for this call must be _Signed_ by the controller.
+		fn rebond(origin, value: StakingBalance<RewardBalanceOf<T>, BalanceOf<T>>) {
+			let controller = ensure_signed(origin)?;
+			election::ensure_window_closed::<T>()?;
+			let ledger = Self::ledger(&controller).ok_or("not a controller")?;
+			let unlocking = match value {
+				StakingBalance::Ring(_) => ledger.ring_unlocking.len(),
+				StakingBalance::Kton(_) => ledger.unlocking.len(),
+			};
+			ensure!(unlocking > 0, "no unlocking chunk");
+
+			let ledger = ledger.rebond(value);
+			Self::update_ledger(&controller, &ledger);
 		}
 
 
 		fn withdraw_unbonded(origin) {
 			let controller = ensure_signed(origin)?;
+			election::ensure_window_closed::<T>()?;
 			let ledger = Self::ledger(&controller).ok_or("not a controller")?;
 			let ledger = ledger.consolidate_unlocked(Self::current_era());
 			Self::update_ledger(&controller, &ledger);
 		}
 
 
-		fn validate(origin, prefs: ValidatorPrefs<RewardBalanceOf<T>>) {
+		fn validate(origin, prefs: ValidatorPrefs) {
 			let controller = ensure_signed(origin)?;
+			election::ensure_window_closed::<T>()?;
 			let ledger = Self::ledger(&controller).ok_or("not a controller")?;
 			let stash = &ledger.stash;
 			ensure!(
@@ -479,36 +949,69 @@ decl_module! {
 				"unstake threshold too large"
 			);
 			<Nominators<T>>::remove(stash);
-			<Validators<T>>::insert(stash, prefs);
+			<PendingCommission<T>>::remove(stash);
+
+			// A validator already backed in the running era keeps its commission until the next
+			// era boundary.
+			let current_commission = if <Validators<T>>::exists(stash) {
+				Some(Self::validators(stash).commission)
+			} else if Self::current_elected().contains(stash) {
+				Some(Self::eras_validator_prefs(&Self::current_era(), stash).commission)
+			} else {
+				None
+			};
+			let commission = match current_commission {
+				Some(commission) => {
+					if commission != prefs.commission {
+						<PendingCommission<T>>::insert(stash, prefs.commission);
+					}
+					commission
+				}
+				None => prefs.commission,
+			};
+			<Validators<T>>::insert(stash, ValidatorPrefs { commission, ..prefs });
 		}
 
 
 		fn nominate(origin, targets: Vec<<T::Lookup as StaticLookup>::Source>) {
 			let controller = ensure_signed(origin)?;
+			election::ensure_window_closed::<T>()?;
 			let ledger = Self::ledger(&controller).ok_or("not a controller")?;
 			let stash = &ledger.stash;
 			ensure!(!targets.is_empty(), "targets cannot be empty");
+			let old_targets = Self::nominators(stash);
 			let targets = targets.into_iter()
 				.take(MAX_NOMINATIONS)
-				.map(T::Lookup::lookup)
+				.map(|t| T::Lookup::lookup(t).and_then(|t|
+					// A blocked validator keeps the nominations it already has.
+					if old_targets.contains(&t) || !Self::validators(&t).blocked {
+						Ok(t)
+					} else {
+						Err("validator is blocking new nominations")
+					}
+				))
 				.collect::<result::Result<Vec<T::AccountId>, &'static str>>()?;
 
 			<Validators<T>>::remove(stash);
+			<PendingCommission<T>>::remove(stash);
 			<Nominators<T>>::insert(stash, targets);
 		}
 
 
 		fn chill(origin) {
 			let controller = ensure_signed(origin)?;
+			election::ensure_window_closed::<T>()?;
 			let ledger = Self::ledger(&controller).ok_or("not a controller")?;
 			let stash = &ledger.stash;
 			<Validators<T>>::remove(stash);
+			<PendingCommission<T>>::remove(stash);
 			<Nominators<T>>::remove(stash);
 		}
 
 
 		fn set_payee(origin, payee: RewardDestination) {
 			let controller = ensure_signed(origin)?;
+			ensure!(payee != RewardDestination::StakedDeprecated, "reward destination is deprecated");
 			let ledger = Self::ledger(&controller).ok_or("not a controller")?;
 			let stash = &ledger.stash;
 			<Payee<T>>::insert(stash, payee);
@@ -524,12 +1027,51 @@ decl_module! {
 			}
 			if controller != old_controller {
 				<Bonded<T>>::insert(&stash, &controller);
-				if let Some(l) = <Ledger<T>>::take(&old_controller) {
+				if let Some(l) = Self::ledger(&old_controller) {
+					<Ledger<T>>::remove(&old_controller);
 					<Ledger<T>>::insert(&controller, l);
 				}
 			}
 		}
 
+		/// Pay out the reward of an era to a validator and its nominators.
+		///
+		/// Any account can call this, the reward goes to the payees of the stakers. Only the last
+		/// `HistoryDepth` eras can be claimed, each of them once per validator.
+		///
+		/// # <weight>
+		/// - Time complexity: `O(N)` where `N` is the number of nominators of the validator.
+		/// # </weight>
+		fn payout_stakers(origin, validator_stash: T::AccountId, era: EraIndex) {
+			let _ = ensure_signed(origin)?;
+
+			let current_era = Self::current_era();
+			ensure!(era < current_era, "era not finished yet");
+			ensure!(
+				era.saturating_add(T::HistoryDepth::get()) >= current_era,
+				"era is out of history"
+			);
+
+			let era_payout = <ErasValidatorReward<T>>::get(&era).ok_or("no reward for this era")?;
+			ensure!(
+				!<ErasRewardClaimed<T>>::get(&era, &validator_stash),
+				"reward already claimed"
+			);
+
+			let era_reward_points = Self::eras_reward_points(&era);
+			let validator_points = era_reward_points.points_of(&validator_stash);
+			ensure!(validator_points > 0, "validator has no reward points for this era");
+
+			<ErasRewardClaimed<T>>::insert(&era, &validator_stash, true);
+
+			let validator_payout = Perbill::from_rational_approximation(
+				validator_points,
+				era_reward_points.total
+			) * era_payout;
+			Self::reward_validator(era, &validator_stash, validator_payout);
+			Self::deposit_event(RawEvent::Payout(validator_stash, era, validator_payout));
+		}
+
 		/// The ideal number of validators.
 		fn set_validator_count(#[compact] new: u32) {
 			<ValidatorCount<T>>::put(new);
@@ -559,9 +1101,107 @@ decl_module! {
 			<Invulnerables<T>>::put(validators);
 		}
 
-		// NOTE: ugly hacking. remove later.
-		fn report_offline_validator(controller: T::AccountId, missed_count: usize) {
-			Self::on_offline_validator(controller, missed_count);
+		/// Force the validators of the next era, in emergency mode. A new era is forced too.
+		///
+		/// The given stashes must be bonded, they are only exposed with their own stake.
+		fn force_set_validators(validators: Vec<T::AccountId>) {
+			ensure!(Self::emergency_mode(), "not in emergency mode");
+			ensure!(!validators.is_empty(), "validators cannot be empty");
+
+			let mut elected = Vec::with_capacity(validators.len());
+			for stash in validators {
+				ensure!(Self::bonded(&stash).is_some(), "validator is not bonded");
+				ensure!(elected.iter().all(|(s, _)| *s != stash), "validator given twice");
+				let own = Self::slashable_balance_of(&stash);
+				elected.push((stash, Exposure { own, total: own, others: vec![] }));
+			}
+
+			// The forced set is queued as an election solution no one can beat.
+			<QueuedElected<T>>::put(elected);
+			<QueuedScore<T>>::put((ExtendedBalance::max_value(), ExtendedBalance::max_value(), 0));
+			Self::apply_force_new_era();
+		}
+
+		/// Cancel deferred slashes, given the era they are applied at (not the era of their
+		/// offence) and their indices in `UnappliedSlashes`. The cancelled slashes are taken back
+		/// from the slashing spans of their stakers.
+		/// Can be called by either the root origin or the `T::SlashCancelOrigin`.
+		///
+		/// # <weight>
+		/// - One storage write, and two reads and a write per staker of the cancelled slashes.
+		/// # </weight>
+		fn cancel_deferred_slash(origin, era: EraIndex, slash_indices: Vec<u32>) {
+			T::SlashCancelOrigin::ensure_origin(origin.clone())
+				.map(|_| ())
+				.or_else(|_| ensure_root(origin))?;
+
+			let mut slash_indices = slash_indices;
+			slash_indices.sort_unstable();
+			slash_indices.dedup();
+			let mut unapplied = <UnappliedSlashes<T>>::get(&era);
+
+			let last_index = *slash_indices.last().ok_or("slash indices cannot be empty")? as usize;
+			ensure!(last_index < unapplied.len(), "slash record index out of bounds");
+
+			for (removed, index) in slash_indices.into_iter().enumerate() {
+				let index = index as usize;
+
+				// if `index` is not duplicate, `removed` must be <= index.
+				slashing::cancel_slash::<T>(&unapplied.remove(index - removed));
+			}
+
+			<UnappliedSlashes<T>>::insert(&era, &unapplied);
+		}
+
+		/// Report a validator which equivocated. The offence is reported in the current session
+		/// and the reporter is given `SlashRewardFraction` of the slashes caused by the report.
+		fn report_equivocation(origin, proof: EquivocationProofOf<T>) {
+			let reporter = ensure_signed(origin)?;
+
+			let equivocation = offences::check_equivocation_proof::<T>(&proof)?;
+			ensure!(!Self::reported_equivocations(&equivocation), "equivocation already reported");
+
+			let (kind, offender, _, _) = equivocation.clone();
+			let offence = Offence {
+				kind,
+				session_index: <session::Module<T>>::current_index(),
+				validator_set_count: <session::Module<T>>::validators().len() as u32,
+				offenders: vec![offender],
+			};
+			<Self as ReportOffence<_>>::report_offence(vec![reporter], offence)?;
+			<ReportedEquivocations<T>>::insert(&equivocation, true);
+		}
+
+		/// Note that a validator is alive in the current session.
+		///
+		/// Submitted unsigned by the offchain worker of the validator, the heartbeat being signed
+		/// with its authority key.
+		fn heartbeat(origin, heartbeat: Heartbeat<T::BlockNumber>, signature: T::AuthoritySignature) {
+			ensure_none(origin)?;
+
+			let authority_id = heartbeat::check_heartbeat::<T>(&heartbeat, &signature)?;
+			<ReceivedHeartbeats<T>>::insert(&heartbeat.session_index, &heartbeat.authority_index, true);
+			Self::deposit_event(RawEvent::HeartbeatReceived(heartbeat.session_index, authority_id));
+		}
+
+		/// Submit the validators elected for the next era with their exposures, during the
+		/// election window.
+		///
+		/// Submitted unsigned by the offchain worker of a validator, the solution being signed
+		/// with its authority key. It is queued if it is valid and better than the queued one.
+		fn submit_election_solution(
+			origin,
+			era: EraIndex,
+			elected: Vec<(T::AccountId, Exposure<T::AccountId, BalanceOf<T>>)>,
+			authority_index: u32,
+			signature: T::AuthoritySignature
+		) {
+			ensure_none(origin)?;
+
+			let score = election::check_solution::<T>(era, &elected, authority_index, &signature)?;
+			<QueuedElected<T>>::put(elected);
+			<QueuedScore<T>>::put(score);
+			Self::deposit_event(RawEvent::ElectionSolutionQueued(era, score.0));
 		}
 	}
 }
@@ -569,18 +1209,68 @@ decl_module! {
 impl<T: Trait> Module<T> {
 	// PUBLIC IMMUTABLES
 
+	/// The ledger of a controller.
+	///
+	/// The ledgers of the stashes which neither validate nor nominate could not be rewritten by
+	/// the migration to version 2, they are read in their previous layout until next written.
+	pub fn ledger(controller: &T::AccountId) -> Option<StakingLedgerOf<T>> {
+		<Ledger<T>>::get(controller).or_else(|| migration::ledger_before_v2::<T>(controller))
+	}
+
 	/// The total balance that can be slashed from a validator controller account as of
 	/// right now.
 	pub fn slashable_balance(who: &T::AccountId) -> BalanceOf<T> {
 		Self::stakers(who).total
 	}
 
+	/// The exposures of the validators elected at era, empty if the era is out of history.
+	pub fn eras_exposures(era: EraIndex) -> Vec<(T::AccountId, Exposure<T::AccountId, BalanceOf<T>>)> {
+		Self::eras_elected(&era)
+			.into_iter()
+			.map(|validator| {
+				let exposure = Self::eras_stakers(&era, &validator);
+				(validator, exposure)
+			})
+			.collect()
+	}
+
 	// MUTABLES (DANGEROUS)
 
-	/// Update the ledger for a controller. This will also update the stash lock.
+	/// Bond more funds of the stash, at most its free balance which is not bonded yet.
+	fn bond_helper(
+		controller: &T::AccountId,
+		mut ledger: StakingLedger<T::AccountId, RewardBalanceOf<T>, BalanceOf<T>>,
+		max_additional: StakingBalance<RewardBalanceOf<T>, BalanceOf<T>>,
+	) {
+		match max_additional {
+			StakingBalance::Ring(r) => {
+				// The RING locked otherwise, e.g. in KTON deposits, can not be bonded too.
+				let stash_balance = T::RewardCurrency::free_balance(&ledger.stash).saturating_sub(
+					T::RewardCurrency::locked_balance(&ledger.stash, STAKING_ID, WithdrawReason::Transfer)
+				);
+				if let Some(extra) = stash_balance.checked_sub(&ledger.total_ring) {
+					let extra = extra.min(r);
+					ledger.total_ring += extra;
+					ledger.active_ring += extra;
+				}
+			},
+			StakingBalance::Kton(k) => {
+				let stash_balance = T::Currency::free_balance(&ledger.stash);
+				if let Some(extra) = stash_balance.checked_sub(&ledger.total) {
+					let extra = extra.min(k);
+					ledger.total += extra;
+					ledger.active += extra;
+				}
+			},
+		}
+
+		Self::update_ledger(controller, &ledger);
+	}
+
+	/// Update the ledger for a controller. This will also update the stash locks.
 	fn update_ledger(
 		controller: &T::AccountId,
-		ledger: &StakingLedger<T::AccountId, BalanceOf<T>>
+		ledger: &StakingLedger<T::AccountId, RewardBalanceOf<T>, BalanceOf<T>>
 	) {
 		T::Currency::set_lock(
 			STAKING_ID,
@@ -589,36 +1279,36 @@ impl<T: Trait> Module<T> {
 			T::BlockNumber::max_value(),
 			WithdrawReasons::all()
 		);
+		T::RewardCurrency::set_lock(
+			STAKING_ID,
+			&ledger.stash,
+			ledger.total_ring,
+			T::Moment::max_value(),
+			WithdrawReasons::all()
+		);
 		<Ledger<T>>::insert(controller, ledger);
 	}
 
-	/// Slash a given validator by a specific amount. Removes the slash from the validator's
-	/// balance by preference, and reduces the nominators' balance if needed.
-	fn slash_validator(stash: &T::AccountId, slash: BalanceOf<T>) {
-		// The exposure (backing stake) information of the validator to be slashed.
-		let exposure = Self::stakers(stash);
-		// The amount we are actually going to slash (can't be bigger than the validator's total
-		// exposure)
-		let slash = slash.min(exposure.total);
-		// The amount we'll slash from the validator's stash directly.
-		let own_slash = exposure.own.min(slash);
-		let (mut imbalance, missing) = T::Currency::slash(stash, own_slash);
-		let own_slash = own_slash - missing;
-		// The amount remaining that we can't slash from the validator, that must be taken from the
-		// nominators.
-		let rest_slash = slash - own_slash;
-		if !rest_slash.is_zero() {
-			// The total to be slashed from the nominators.
-			let total = exposure.total - exposure.own;
-			if !total.is_zero() {
-				for i in exposure.others.iter() {
-					let per_u64 = Perbill::from_rational_approximation(i.value, total);
-					// best effort - not much that can be done on fail.
-					imbalance.subsume(T::Currency::slash(&i.who, per_u64 * rest_slash).0)
-				}
+	/// Slash a given validator by a specific amount. The slash is taken from the validator's
+	/// balance by preference, and from the nominators' balance if needed.
+	///
+	/// The slash is deferred by `SlashDeferDuration` eras, it can be cancelled until then.
+	fn slash_validator(
+		era: EraIndex,
+		stash: &T::AccountId,
+		slash: BalanceOf<T>,
+		reporters: Vec<T::AccountId>,
+	) {
+		if let Some(unapplied) = slashing::compute_slash::<T>(era, stash, slash, reporters) {
+			let slash_defer_duration = T::SlashDeferDuration::get();
+			if slash_defer_duration == 0 {
+				slashing::apply_slash::<T>(unapplied);
+			} else {
+				let apply_at = Self::current_era() + slash_defer_duration;
+				<UnappliedSlashes<T>>::mutate(&apply_at, |slashes| slashes.push(unapplied));
+				Self::deposit_event(RawEvent::SlashDeferred(stash.clone(), apply_at));
 			}
 		}
-		T::Slash::on_unbalanced(imbalance);
 	}
 
 	/// Actually make a payment to a staker. This uses the currency's reward function
@@ -632,21 +1322,45 @@ impl<T: Trait> Module<T> {
 				),
 			RewardDestination::Stash =>
 				T::RewardCurrency::deposit_into_existing(stash, amount).ok(),
-			RewardDestination::StakedDeprecated => None,
+			// The payee of a stash which was idle during the migration, it is `Staked` from now on.
+			RewardDestination::StakedDeprecated => {
+				<Payee<T>>::insert(stash, RewardDestination::Staked);
+				Self::make_staked_payout(stash, amount)
+			},
+			RewardDestination::Staked => Self::make_staked_payout(stash, amount),
 		}
 	}
 
+	/// Pay the reward into the stash, lock it in a KTON deposit and bond the KTON minted.
+	///
+	/// The RING reward is kept free in the stash if it can not be deposited, see `RewardNotStaked`.
+	fn make_staked_payout(stash: &T::AccountId, amount: RewardBalanceOf<T>) -> Option<PositiveImbalanceOf<T>> {
+		let controller = Self::bonded(stash)?;
+		let mut ledger = Self::ledger(&controller)?;
+		let imbalance = T::RewardCurrency::deposit_into_existing(stash, amount).ok()?;
+
+		if let Ok(extra) = T::Currency::deposit_ring(stash, amount, T::RewardDepositMonths::get()) {
+			ledger.total += extra;
+			ledger.active += extra;
+			Self::update_ledger(&controller, &ledger);
+		} else {
+			Self::deposit_event(RawEvent::RewardNotStaked(stash.clone(), amount));
+		}
+
+		Some(imbalance)
+	}
+
 	/// Reward a given validator by a specific amount. Add the reward to the validator's, and its
-	/// nominators' balance, pro-rata based on their exposure, after having removed the validator's
-	/// pre-payout cut.
-	fn reward_validator(stash: &T::AccountId, reward: RewardBalanceOf<T>) {
-		let off_the_table = reward.min(Self::validators(stash).validator_payment);
+	/// nominators' balance, pro-rata based on their exposure at `era`, after having removed the
+	/// validator's commission.
+	fn reward_validator(era: EraIndex, stash: &T::AccountId, reward: RewardBalanceOf<T>) {
+		let off_the_table = Self::eras_validator_prefs(&era, stash).commission * reward;
 		let reward = reward - off_the_table;
 		let mut imbalance = <PositiveImbalanceOf<T>>::zero();
 		let validator_cut = if reward.is_zero() {
 			Zero::zero()
 		} else {
-			let exposure = Self::stakers(stash);
+			let exposure = Self::eras_stakers(&era, stash);
 			let total = exposure.total.max(One::one());
 
 			for i in &exposure.others {
@@ -663,36 +1377,65 @@ impl<T: Trait> Module<T> {
 
 	/// Session has just ended. Provide the validator set for the next session if it's an era-end.
 	fn new_session(session_index: SessionIndex) -> Option<Vec<T::AccountId>> {
+		<SessionStart<T>>::put(<system::Module<T>>::block_number());
+
 		// accumulate good session reward
 //		let reward = Self::current_session_reward();
 //		<CurrentEraReward<T>>::mutate(|r| *r += reward);
 
-		if <ForceNewEra<T>>::take() || session_index % T::SessionsPerEra::get() == 0 {
-			Self::new_era()
+		let maybe_new_validators = if <ForceNewEra<T>>::take() || session_index % T::SessionsPerEra::get() == 0 {
+			Self::new_era(session_index)
 		} else {
 			None
+		};
+
+		// The election of the next era is computed offchain during its last session.
+		if (session_index + 1) % T::SessionsPerEra::get() == 0 {
+			election::open_election_window::<T>();
 		}
+
+		maybe_new_validators
 	}
 
 	/// The era has changed - enact new staking set.
 	///
 	/// NOTE: This always happens immediately before a session change to ensure that new validators
 	/// get a chance to set their session keys.
-	fn new_era() -> Option<Vec<T::AccountId>> {
-		// Payout
-		let reward = Self::session_reward() * Self::current_era_total_reward();
+	fn new_era(start_session_index: SessionIndex) -> Option<Vec<T::AccountId>> {
+		// Payout, unless the era was run by the previous validators in emergency mode.
+		let era = Self::current_era();
+		let reward = if Self::emergency_mode() {
+			Zero::zero()
+		} else {
+			Self::session_reward() * Self::current_era_total_reward()
+		};
+		<ErasTotalReward<T>>::insert(&era, reward);
 		if !reward.is_zero() {
-			let validators = Self::current_elected();
-			let len = validators.len() as u32; // validators length can never overflow u64
-			let len: RewardBalanceOf<T> = len.into();
-			let block_reward_per_validator = reward / len;
-			for v in validators.iter() {
-				Self::reward_validator(v, block_reward_per_validator);
+			// The era reward is split between stakers, KTON holders and the treasury, which gets
+			// what is left. The stakers claim their part later on with `payout_stakers`, unless no
+			// validator earned a point in the era: nobody could claim it, so the treasury gets it.
+			let mut remaining_reward = reward;
+			let stakers_reward = T::StakersRatio::get() * reward;
+			if !stakers_reward.is_zero() && Self::eras_reward_points(&era).total > 0 {
+				remaining_reward -= stakers_reward;
+				<ErasValidatorReward<T>>::insert(&era, stakers_reward);
+				Self::deposit_event(RawEvent::Reward(era, stakers_reward));
 			}
-			Self::deposit_event(RawEvent::Reward(block_reward_per_validator));
 
-			T::Currency::reward_to_pot(reward);
-			// TODO: reward to treasury
+			let kton_holders_reward = (T::KtonHoldersRatio::get() * reward).min(remaining_reward);
+			if !kton_holders_reward.is_zero() {
+				remaining_reward -= kton_holders_reward;
+				T::Currency::reward_to_pot(kton_holders_reward);
+			}
+
+			if !remaining_reward.is_zero() {
+				let imbalance = T::RewardCurrency::deposit_creating(
+					&T::TreasuryAccount::get(),
+					remaining_reward
+				);
+				T::Reward::on_unbalanced(imbalance);
+				Self::deposit_event(RawEvent::TreasuryReward(remaining_reward));
+			}
 		}
 
 		// check if ok to change epoch
@@ -701,13 +1444,59 @@ impl<T: Trait> Module<T> {
 		}
 		// Increment current era.
 		<CurrentEra<T>>::mutate(|s| *s += 1);
+		<ErasStartSessionIndex<T>>::insert(&Self::current_era(), start_session_index);
+
+		// Apply the slashes deferred to this era.
+		for unapplied in <UnappliedSlashes<T>>::take(&Self::current_era()) {
+			slashing::apply_slash::<T>(unapplied);
+		}
+
+		// Clean up the era which just went out of history.
+		if let Some(old_era) = (era + 1).checked_sub(T::HistoryDepth::get() + 1) {
+			Self::clear_era_information(old_era);
+		}
+		if let Some(old_era) = Self::current_era().checked_sub(T::OfflineRecordsDepth::get()) {
+			<OfflineRecords<T>>::remove_prefix(&old_era);
+		}
 
 		// Reassign all Stakers.
 		let (_, maybe_new_validators) = Self::select_validators();
 
+		// The preferences of the new era are set, the pending commissions apply from the next one.
+		for (stash, commission) in <PendingCommission<T>>::enumerate().collect::<Vec<_>>() {
+			<PendingCommission<T>>::remove(&stash);
+			if <Validators<T>>::exists(&stash) {
+				<Validators<T>>::mutate(&stash, |prefs| prefs.commission = commission);
+			}
+		}
+
 		maybe_new_validators
 	}
 
+	/// Clear all era information for given era.
+	fn clear_era_information(era: EraIndex) {
+		<ErasElected<T>>::remove(&era);
+		<ErasSlotStake<T>>::remove(&era);
+		<ErasTotalReward<T>>::remove(&era);
+		<ErasStakers<T>>::remove_prefix(&era);
+		<ErasValidatorPrefs<T>>::remove_prefix(&era);
+		<ErasValidatorReward<T>>::remove(&era);
+		<ErasRewardPoints<T>>::remove(&era);
+		<ErasRewardClaimed<T>>::remove_prefix(&era);
+		<ErasStartSessionIndex<T>>::remove(&era);
+		<OffenceReports<T>>::remove_prefix(&era);
+	}
+
+	/// The era of a session, if it is within the last `HistoryDepth` eras.
+	fn era_of_session(session_index: SessionIndex) -> Option<EraIndex> {
+		let current_era = Self::current_era();
+		let oldest_era = current_era.saturating_sub(T::HistoryDepth::get());
+		(oldest_era..=current_era).rev().find(|era|
+			// The genesis era has no record, it starts with the first session.
+			Self::eras_start_session_index(era).unwrap_or(0) <= session_index
+		)
+	}
+
 	fn new_epoch() {
 		<EpochIndex<T>>::put(Self::epoch_index() + One::one());
 		if let Ok(next_era_reward) =  minting::compute_current_era_reward::<T>() {
@@ -716,199 +1505,348 @@ impl<T: Trait> Module<T> {
 		}
 	}
 
+	/// The voting power of the funds bonded by a stash, including the ones being unbonded.
+	///
+	/// It is expressed in KTON, the bonded RING counting for `RingKtonRatio` less.
 	fn slashable_balance_of(stash: &T::AccountId) -> BalanceOf<T> {
-		Self::bonded(stash).and_then(Self::ledger).map(|l| l.total).unwrap_or_default()
+		Self::bonded(stash)
+			.and_then(Self::ledger)
+			.map(|l| l.total.saturating_add(Self::ring_to_power(l.total_ring)))
+			.unwrap_or_default()
+	}
+
+	/// The voting power of some RING, in KTON.
+	fn ring_to_power(ring: RewardBalanceOf<T>) -> BalanceOf<T> {
+		let ring: u128 = ring.try_into().unwrap_or(u128::max_value());
+		let power = ring / T::RingKtonRatio::get().max(1) as u128;
+		power.try_into().unwrap_or(BalanceOf::<T>::max_value())
 	}
 
 	/// Select a new validator set from the assembled stakers and their role preferences.
 	///
+	/// The best solution submitted by the offchain workers, or the set forced by root, is used if
+	/// there is one, otherwise the election is run on-chain. If it fails, the emergency mode is
+	/// entered.
+	///
 	/// Returns the new `SlotStake` value.
 	fn select_validators() -> (BalanceOf<T>, Option<Vec<T::AccountId>>) {
-		let maybe_elected_set = elect::<T, _, _, _>(
-			Self::validator_count() as usize,
-			Self::minimum_validator_count().max(1) as usize,
-			<Validators<T>>::enumerate(),
-			<Nominators<T>>::enumerate(),
-			Self::slashable_balance_of,
+		let maybe_elected = <QueuedElected<T>>::take().or_else(||
+			Self::do_phragmen(
+				<Validators<T>>::enumerate(),
+				<Nominators<T>>::enumerate(),
+				Self::slashable_balance_of,
+				false,
+			)
 		);
+		election::close_election_window::<T>();
 
-		if let Some(elected_set) = maybe_elected_set {
-			let elected_stashes = elected_set.0;
-			let assignments = elected_set.1;
-
-			// helper closure.
-			let to_balance = |b: ExtendedBalance|
-				<T::CurrencyToVote as Convert<ExtendedBalance, BalanceOf<T>>>::convert(b);
-			let to_votes = |b: BalanceOf<T>|
-				<T::CurrencyToVote as Convert<BalanceOf<T>, u64>>::convert(b) as ExtendedBalance;
-
-			// The return value of this is safe to be converted to u64.
-			// The original balance, `b` is within the scope of u64. It is just extended to u128
-			// to be properly multiplied by a ratio, which will lead to another value
-			// less than u64 for sure. The result can then be safely passed to `to_balance`.
-			// For now the backward convert is used. A simple `TryFrom<u64>` is also safe.
-			let ratio_of = |b, p| (p as ExtendedBalance).saturating_mul(to_votes(b)) / ACCURACY;
-
-			// Compute the actual stake from nominator's ratio.
-			let mut assignments_with_stakes = assignments.iter().map(|(n, a)|(
-				n.clone(),
-				Self::slashable_balance_of(n),
-				a.iter().map(|(acc, r)| (
-					acc.clone(),
-					*r,
-					to_balance(ratio_of(Self::slashable_balance_of(n), *r)),
-				))
-				.collect::<Vec<Assignment<T>>>()
-			)).collect::<Vec<(T::AccountId, BalanceOf<T>, Vec<Assignment<T>>)>>();
-
-			// update elected candidate exposures.
-			let mut exposures = <ExpoMap<T>>::new();
-			elected_stashes
-				.iter()
-				.map(|e| (e, Self::slashable_balance_of(e)))
-				.for_each(|(e, s)| {
-					let item = Exposure { own: s, total: s, ..Default::default() };
-					exposures.insert(e.clone(), item);
-				});
-
-			for (n, _, assignment) in &assignments_with_stakes {
-				for (c, _, s) in assignment {
-					if let Some(expo) = exposures.get_mut(c) {
-						// NOTE: simple example where this saturates:
-						// candidate with max_value stake. 1 nominator with max_value stake.
-						// Nuked. Sadly there is not much that we can do about this.
-						// See this test: phragmen_should_not_overflow_xxx()
-						expo.total = expo.total.saturating_add(*s);
-						expo.others.push( IndividualExposure { who: n.clone(), value: *s } );
-					}
-				}
-			}
-
-			// This optimization will most likely be only applied off-chain.
-			let do_equalize = false;
-			if do_equalize {
-				let tolerance = 10 as u128;
-				let iterations = 10 as usize;
-				phragmen::equalize::<T>(
-					&mut assignments_with_stakes,
-					&mut exposures,
-					tolerance,
-					iterations
-				);
-			}
-
-			// Clear Stakers and reduce their slash_count.
+		if let Some(elected) = maybe_elected {
+			// Clear Stakers.
 			for v in Self::current_elected().iter() {
 				<Stakers<T>>::remove(v);
-				let slash_count = <SlashCount<T>>::take(v);
-				if slash_count > 1 {
-					<SlashCount<T>>::insert(v, slash_count - 1);
-				}
 			}
 
 			// Populate Stakers and figure out the minimum stake behind a slot.
+			// The exposures and preferences are also kept for paying out the era later on.
+			let era = Self::current_era();
 			let mut slot_stake = BalanceOf::<T>::max_value();
-			for (c, e) in exposures.iter() {
+			for (c, e) in elected.iter() {
 				if e.total < slot_stake {
 					slot_stake = e.total;
 				}
 				<Stakers<T>>::insert(c.clone(), e.clone());
+				<ErasStakers<T>>::insert(&era, c, e.clone());
+				<ErasValidatorPrefs<T>>::insert(&era, c, Self::validators(c));
 			}
 			<SlotStake<T>>::put(&slot_stake);
+			<ErasSlotStake<T>>::insert(&era, slot_stake);
 
 			// Set the new validator set.
+			let elected_stashes = elected.into_iter().map(|(s, _)| s).collect::<Vec<_>>();
 			<CurrentElected<T>>::put(&elected_stashes);
+			<ErasElected<T>>::insert(&era, &elected_stashes);
 			let validators = elected_stashes.into_iter()
 				.map(|s| Self::bonded(s).unwrap_or_default())
 				.collect::<Vec<_>>();
+			<EmergencyMode<T>>::kill();
 			(slot_stake, Some(validators))
 		} else {
 			// There were not enough candidates for even our minimal level of functionality.
-			// This is bad.
-			// We should probably disable all functionality except for block production
-			// and let the chain keep producing blocks until we can decide on a sufficiently
-			// substantial set.
-			// TODO: #2494
+			// The previous validators keep producing blocks, with the same exposures so that
+			// they can still be slashed, until a set is elected or forced by root.
+			let era = Self::current_era();
+			let previous_era = era.saturating_sub(1);
+			let previous = Self::current_elected();
+			for v in &previous {
+				<ErasStakers<T>>::insert(&era, v, Self::stakers(v));
+				<ErasValidatorPrefs<T>>::insert(&era, v, Self::eras_validator_prefs(&previous_era, v));
+			}
+			<ErasSlotStake<T>>::insert(&era, Self::slot_stake());
+			<ErasElected<T>>::insert(&era, previous);
+
+			<EmergencyMode<T>>::put(true);
+			Self::deposit_event(RawEvent::ElectionFailed(era));
 			(Self::slot_stake(), None)
 		}
 	}
 
+	/// Run the Phragmén election on the given candidates and nominators, whose stakes are given by
+	/// `stake_of`. The stake is equalized between the elected validators if `do_equalize`.
+	///
+	/// Returns the elected stashes with their exposures, in the order they were elected.
+	fn do_phragmen<FV, FN, FS>(
+		validator_iter: FV,
+		nominator_iter: FN,
+		stake_of: FS,
+		do_equalize: bool,
+	) -> Option<Vec<(T::AccountId, Exposure<T::AccountId, BalanceOf<T>>)>> where
+		FV: Iterator<Item=(T::AccountId, ValidatorPrefs)>,
+		FN: Iterator<Item=(T::AccountId, Vec<T::AccountId>)>,
+		for <'r> FS: Fn(&'r T::AccountId) -> BalanceOf<T>,
+	{
+		let (elected_stashes, assignments) = elect::<T, _, _, _>(
+			Self::validator_count() as usize,
+			Self::minimum_validator_count().max(1) as usize,
+			validator_iter,
+			nominator_iter,
+			&stake_of,
+		)?;
+
+		// helper closure.
+		let to_balance = |b: ExtendedBalance|
+			<T::CurrencyToVote as Convert<ExtendedBalance, BalanceOf<T>>>::convert(b);
+		let to_votes = |b: BalanceOf<T>|
+			<T::CurrencyToVote as Convert<BalanceOf<T>, u64>>::convert(b) as ExtendedBalance;
+
+		// The return value of this is safe to be converted to u64.
+		// The original balance, `b` is within the scope of u64. It is just extended to u128
+		// to be properly multiplied by a ratio, which will lead to another value
+		// less than u64 for sure. The result can then be safely passed to `to_balance`.
+		// For now the backward convert is used. A simple `TryFrom<u64>` is also safe.
+		let ratio_of = |b, p| (p as ExtendedBalance).saturating_mul(to_votes(b)) / ACCURACY;
+
+		// Compute the actual stake from nominator's ratio.
+		let mut assignments_with_stakes = assignments.iter().map(|(n, a)|(
+			n.clone(),
+			stake_of(n),
+			a.iter().map(|(acc, r)| (
+				acc.clone(),
+				*r,
+				to_balance(ratio_of(stake_of(n), *r)),
+			))
+			.collect::<Vec<Assignment<T>>>()
+		)).collect::<Vec<(T::AccountId, BalanceOf<T>, Vec<Assignment<T>>)>>();
+
+		// update elected candidate exposures.
+		let mut exposures = <ExpoMap<T>>::new();
+		elected_stashes
+			.iter()
+			.map(|e| (e, stake_of(e)))
+			.for_each(|(e, s)| {
+				let item = Exposure { own: s, total: s, ..Default::default() };
+				exposures.insert(e.clone(), item);
+			});
+
+		for (n, _, assignment) in &assignments_with_stakes {
+			for (c, _, s) in assignment {
+				if let Some(expo) = exposures.get_mut(c) {
+					// NOTE: simple example where this saturates:
+					// candidate with max_value stake. 1 nominator with max_value stake.
+					// Nuked. Sadly there is not much that we can do about this.
+					// See this test: phragmen_should_not_overflow_xxx()
+					expo.total = expo.total.saturating_add(*s);
+					expo.others.push( IndividualExposure { who: n.clone(), value: *s } );
+				}
+			}
+		}
+
+		// Too heavy on-chain, this is done by the offchain workers.
+		if do_equalize {
+			let tolerance = 10 as u128;
+			let iterations = 10 as usize;
+			phragmen::equalize::<T>(
+				&mut assignments_with_stakes,
+				&mut exposures,
+				tolerance,
+				iterations
+			);
+		}
+
+		// Only the nominators with the highest stake are exposed.
+		let max_nominators = T::MaxNominatorRewardedPerValidator::get() as usize;
+		Some(elected_stashes.into_iter()
+			.map(|e| {
+				let mut exposure = exposures.remove(&e).unwrap_or_default();
+				if exposure.others.len() > max_nominators {
+					exposure.others.sort_by(|a, b| stake_of(&b.who).cmp(&stake_of(&a.who)));
+					exposure.others.truncate(max_nominators);
+					exposure.total = exposure.others.iter()
+						.fold(exposure.own, |total, i| total.saturating_add(i.value));
+				}
+				(e, exposure)
+			})
+			.collect())
+	}
+
+	/// The number of blocks left in the current session, at least one.
+	pub(crate) fn session_longevity() -> TransactionLongevity {
+		let session_end = Self::session_start() + T::SessionLength::get();
+		let remaining = session_end.saturating_sub(<system::Module<T>>::block_number());
+		remaining.try_into().unwrap_or(TransactionLongevity::max_value()).max(1)
+	}
+
 	fn apply_force_new_era() {
 		<ForceNewEra<T>>::put(true);
 	}
 
+	/// Add reward points to validators using their stash account ID.
+	///
+	/// Validators are keyed by stash account ID and must be in the current elected set.
+	///
+	/// For each element in the iterator the given number of points in u32 is added to the
+	/// validator, thus duplicates are handled.
+	///
+	/// This is called by the block authoring logic through `reward_slot_author` for every
+	/// authored block, so validators which are offline or skip their slots earn nothing.
+	pub fn reward_by_ids(validators_points: impl IntoIterator<Item = (T::AccountId, u32)>) {
+		let current_elected = Self::current_elected();
+		<ErasRewardPoints<T>>::mutate(&Self::current_era(), |rewards| {
+			for (validator, points) in validators_points.into_iter() {
+				if current_elected.contains(&validator) {
+					rewards.add_points(validator, points);
+				}
+			}
+		});
+	}
+
+	/// Give a reward point to the validator which authored the block of `slot`.
+	///
+	/// The authorities take their slots in turn, see `offences::session_authorities`.
+	pub fn reward_slot_author(slot: usize) {
+		let authorities = offences::session_authorities::<T>().unwrap_or_default();
+		if authorities.is_empty() {
+			return;
+		}
+
+		let (_, controller) = &authorities[slot % authorities.len()];
+		if let Some(ledger) = Self::ledger(controller) {
+			Self::reward_by_ids(vec![(ledger.stash, 1)]);
+		}
+	}
+
+	/// The number of times a validator has been reported offline, decayed by one for every era
+	/// that has passed since each report.
+	pub fn slash_count(stash: &T::AccountId) -> u32 {
+		let current_era = Self::current_era();
+		let oldest_era = (current_era + 1).saturating_sub(T::OfflineRecordsDepth::get());
+		(oldest_era..=current_era)
+			.map(|era| Self::offline_records(&era, stash).saturating_sub(current_era - era))
+			.fold(0, |count, c| count.saturating_add(c))
+	}
+
 	/// Call when a validator is determined to be offline. `count` is the
 	/// number of offenses the validator has committed.
 	///
 	/// NOTE: This is called with the controller (not the stash) account id.
 	pub fn on_offline_validator(controller: T::AccountId, count: usize) {
 		if let Some(l) = Self::ledger(&controller) {
-			let stash = l.stash;
+			Self::note_offline(Self::current_era(), &l.stash, count as u32);
+		}
+	}
 
-			// Early exit if validator is invulnerable.
-			if Self::invulnerables().contains(&stash) {
-				return
-			}
+	/// Record that a validator was offline `count` times in `era`. It is given an offline warning
+	/// until its slash count exceeds the grace and its unstake threshold, then it is slashed and
+	/// disabled.
+	pub(crate) fn note_offline(era: EraIndex, stash: &T::AccountId, count: u32) {
+		// Early exit if validator is invulnerable.
+		if Self::invulnerables().contains(stash) {
+			return
+		}
 
-			let slash_count = Self::slash_count(&stash);
-			let new_slash_count = slash_count + count as u32;
-			<SlashCount<T>>::insert(&stash, new_slash_count);
-			let grace = Self::offline_slash_grace();
-
-			if RECENT_OFFLINE_COUNT > 0 {
-				let item = (stash.clone(), <system::Module<T>>::block_number(), count as u32);
-				<RecentlyOffline<T>>::mutate(|v| if v.len() >= RECENT_OFFLINE_COUNT {
-					let index = v.iter()
-						.enumerate()
-						.min_by_key(|(_, (_, block, _))| block)
-						.expect("v is non-empty; qed")
-						.0;
-					v[index] = item;
-				} else {
-					v.push(item);
-				});
+		let slash_count = Self::slash_count(stash);
+		let new_slash_count = slash_count.saturating_add(count);
+		<OfflineRecords<T>>::mutate(&era, stash, |c| *c = c.saturating_add(count));
+		let grace = Self::offline_slash_grace();
+
+		let prefs = Self::validators(stash);
+		let unstake_threshold = prefs.unstake_threshold.min(MAX_UNSTAKE_THRESHOLD);
+		let max_slashes = grace + unstake_threshold;
+
+		let event = if new_slash_count > max_slashes {
+			let slash_exposure = Self::eras_stakers(&era, stash).total;
+			let offline_slash_base = Self::offline_slash() * slash_exposure;
+			// They're bailing.
+			let slash = offline_slash_base
+				// Multiply slash_mantissa by 2^(unstake_threshold with upper bound)
+				.checked_shl(unstake_threshold)
+				.map(|x| x.min(slash_exposure))
+				.unwrap_or(slash_exposure);
+			let _ = Self::slash_validator(era, stash, slash, vec![]);
+			if let Some(controller) = Self::bonded(stash) {
+				let _ = <session::Module<T>>::disable(&controller);
 			}
 
-			let prefs = Self::validators(&stash);
-			let unstake_threshold = prefs.unstake_threshold.min(MAX_UNSTAKE_THRESHOLD);
-			let max_slashes = grace + unstake_threshold;
-
-			let event = if new_slash_count > max_slashes {
-				let slash_exposure = Self::stakers(&stash).total;
-				let offline_slash_base = Self::offline_slash() * slash_exposure;
-				// They're bailing.
-				let slash = offline_slash_base
-					// Multiply slash_mantissa by 2^(unstake_threshold with upper bound)
-					.checked_shl(unstake_threshold)
-					.map(|x| x.min(slash_exposure))
-					.unwrap_or(slash_exposure);
-				let _ = Self::slash_validator(&stash, slash);
-				let _ = <session::Module<T>>::disable(&controller);
+			RawEvent::OfflineSlash(stash.clone(), slash)
+		} else {
+			RawEvent::OfflineWarning(stash.clone(), slash_count)
+		};
 
-				RawEvent::OfflineSlash(stash.clone(), slash)
-			} else {
-				RawEvent::OfflineWarning(stash.clone(), slash_count)
-			};
+		Self::deposit_event(event);
+	}
+}
+
+impl<T: Trait> ValidateUnsigned for Module<T> {
+	type Call = Call<T>;
 
-			Self::deposit_event(event);
+	fn validate_unsigned(call: &Self::Call) -> TransactionValidity {
+		match call {
+			Call::heartbeat(heartbeat, signature) =>
+				heartbeat::validate_heartbeat::<T>(heartbeat, signature),
+			Call::submit_election_solution(era, elected, authority_index, signature) =>
+				election::validate_solution::<T>(*era, elected, *authority_index, signature),
+			_ => TransactionValidity::Invalid(0),
 		}
 	}
 }
 
 impl<T: Trait> OnSessionEnding<T::AccountId> for Module<T> {
 	fn on_session_ending(i: SessionIndex) -> Option<Vec<T::AccountId>> {
+		Self::check_liveness(i);
 		Self::new_session(i + 1)
 	}
 }
 
 impl<T: Trait> OnFreeBalanceZero<T::AccountId> for Module<T> {
 	fn on_free_balance_zero(stash: &T::AccountId) {
+		// Both currencies call this, their locks being already removed. Only the part of the
+		// ledger in a reaped currency is dropped, the stash is cleared once both are.
+		if let Some(controller) = Self::bonded(stash) {
+			if let Some(mut ledger) = Self::ledger(&controller) {
+				if T::RewardCurrency::free_balance(stash).is_zero() {
+					ledger.total_ring = Zero::zero();
+					ledger.active_ring = Zero::zero();
+					ledger.ring_unlocking.clear();
+				}
+				if T::Currency::free_balance(stash).is_zero() {
+					ledger.total = Zero::zero();
+					ledger.active = Zero::zero();
+					ledger.unlocking.clear();
+				}
+				if !ledger.total_ring.is_zero() || !ledger.total.is_zero() {
+					<Ledger<T>>::insert(&controller, ledger);
+					return;
+				}
+			}
+		}
+
 		if let Some(controller) = <Bonded<T>>::take(stash) {
 			<Ledger<T>>::remove(&controller);
 		}
 		<Payee<T>>::remove(stash);
-		<SlashCount<T>>::remove(stash);
 		<Validators<T>>::remove(stash);
+		<PendingCommission<T>>::remove(stash);
 		<Nominators<T>>::remove(stash);
+		<SlashingSpans<T>>::remove(stash);
+		<SpanSlash<T>>::remove_prefix(stash);
 	}
 }
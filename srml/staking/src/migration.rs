@@ -0,0 +1,280 @@
+//! Storage migrations for the staking module.
+//!
+//! They are applied on the first block of a runtime which bumps `CURRENT_VERSION`. `Validators`
+//! and `Nominators` are the only unbounded maps they go through: they are enumerated once and
+//! rewritten at most once, whatever the version the storage comes from. The other items are
+//! bounded by `HistoryDepth`.
+//!
+//! Version 1 rewrote `RewardDestination::StakedDeprecated`, whose rewards were dropped, as `Staked`.
+
+use super::*;
+
+/// The current version of the storage layout. New chains start with it.
+pub const CURRENT_VERSION: u32 = 5;
+
+/// Apply all the migrations the storage has not gone through yet.
+pub fn on_runtime_upgrade<T: Trait>() {
+	let version = <StorageVersion<T>>::get();
+	if version >= CURRENT_VERSION {
+		return;
+	}
+
+	// The linked map is unlinked with its old layout before being relinked with the new one, in
+	// the same order.
+	let validators = if version < 4 {
+		let validators = migrate_validator_prefs::<T>(version);
+		for (stash, prefs) in validators.iter().rev() {
+			<Validators<T>>::insert(stash, prefs);
+		}
+		migrate_eras_validator_prefs::<T>(version);
+		validators.into_iter().map(|(stash, _)| stash).collect::<Vec<_>>()
+	} else {
+		<Validators<T>>::enumerate().map(|(stash, _)| stash).collect::<Vec<_>>()
+	};
+	let nominators = <Nominators<T>>::enumerate().map(|(stash, _)| stash);
+	let stakers = validators.iter().cloned().chain(nominators).collect::<Vec<_>>();
+	if version < 1 {
+		migrate_payees::<T>(&stakers);
+	}
+	if version < 2 {
+		migrate_ledgers::<T>(&stakers);
+	}
+	if version < 5 {
+		migrate_offline_records::<T>(validators);
+	}
+
+	<StorageVersion<T>>::put(CURRENT_VERSION);
+}
+
+/// The storage items whose layout changed, as they were before each version.
+pub(crate) mod deprecated {
+	pub mod v1 {
+		use super::super::*;
+
+		/// `StakingLedger` before version 2, when only KTON could be bonded.
+		#[derive(Encode, Decode)]
+		pub struct StakingLedger<AccountId, Balance: HasCompact> {
+			pub stash: AccountId,
+			#[codec(compact)]
+			pub total: Balance,
+			#[codec(compact)]
+			pub active: Balance,
+			pub unlocking: Vec<UnlockChunk<Balance>>,
+		}
+
+		pub struct Module<T: Trait>(rstd::marker::PhantomData<T>);
+
+		decl_storage! {
+			trait Store for Module<T: Trait> as Staking {
+				pub Ledger: map T::AccountId => Option<StakingLedger<T::AccountId, BalanceOf<T>>>;
+			}
+		}
+	}
+
+	pub mod v2 {
+		use super::super::*;
+
+		/// `ValidatorPrefs` before version 3.
+		#[derive(Encode, Decode, Default)]
+		pub struct ValidatorPrefs<Balance: HasCompact> {
+			#[codec(compact)]
+			pub unstake_threshold: u32,
+			#[codec(compact)]
+			pub validator_payment: Balance,
+		}
+
+		pub struct Module<T: Trait>(rstd::marker::PhantomData<T>);
+
+		decl_storage! {
+			trait Store for Module<T: Trait> as Staking {
+				pub Validators: linked_map T::AccountId => ValidatorPrefs<RewardBalanceOf<T>>;
+				pub ErasValidatorPrefs:
+					double_map EraIndex, twox_128(T::AccountId) => ValidatorPrefs<RewardBalanceOf<T>>;
+			}
+		}
+	}
+
+	pub mod v3 {
+		use super::super::*;
+
+		/// `ValidatorPrefs` before version 4.
+		#[derive(Encode, Decode, Default)]
+		pub struct ValidatorPrefs {
+			#[codec(compact)]
+			pub unstake_threshold: u32,
+			pub commission: Perbill,
+		}
+
+		pub struct Module<T: Trait>(rstd::marker::PhantomData<T>);
+
+		decl_storage! {
+			trait Store for Module<T: Trait> as Staking {
+				pub Validators: linked_map T::AccountId => ValidatorPrefs;
+				pub ErasValidatorPrefs: double_map EraIndex, twox_128(T::AccountId) => ValidatorPrefs;
+			}
+		}
+	}
+
+	pub mod v4 {
+		use super::super::*;
+
+		pub struct Module<T: Trait>(rstd::marker::PhantomData<T>);
+
+		decl_storage! {
+			trait Store for Module<T: Trait> as Staking {
+				pub SlashCount: map T::AccountId => u32;
+				pub RecentlyOffline: Vec<(T::AccountId, T::BlockNumber, u32)>;
+			}
+		}
+	}
+}
+
+/// The commission a `validator_payment` was of the average reward of the `count` validators.
+///
+/// A payment above the average reward took all of it, the commission is clamped to 100%. Without
+/// any reward the payment took nothing, the commission is zero.
+fn commission_of<T: Trait>(
+	validator_payment: RewardBalanceOf<T>,
+	reward: RewardBalanceOf<T>,
+	count: usize,
+) -> Perbill {
+	let average_reward = reward / (count.max(1) as u32).into();
+	if average_reward.is_zero() {
+		Perbill::zero()
+	} else {
+		Perbill::from_rational_approximation(validator_payment.min(average_reward), average_reward)
+	}
+}
+
+/// The reward of the stakers in the running era, estimated from the current era reward.
+///
+/// The actual reward is only set when the era ends, a new epoch may change the era reward before.
+/// The commissions migrated from the estimate are then off by as much, validators can set theirs
+/// again with `validate`.
+fn estimated_era_reward<T: Trait>() -> RewardBalanceOf<T> {
+	T::StakersRatio::get() * (<Module<T>>::session_reward() * <Module<T>>::current_era_total_reward())
+}
+
+/// Take the preferences of the validators out of their layout at `version`, in the current one.
+///
+/// Version 3 replaced the absolute `validator_payment` by a `commission`: the share the payment was
+/// of the average reward of a validator. Version 4 let validators block new nominations, none of
+/// them does.
+fn migrate_validator_prefs<T: Trait>(version: u32) -> Vec<(T::AccountId, ValidatorPrefs)> {
+	if version < 3 {
+		let validators = <deprecated::v2::Validators<T>>::enumerate().collect::<Vec<_>>();
+		for (stash, _) in &validators {
+			<deprecated::v2::Validators<T>>::remove(stash);
+		}
+		let reward = estimated_era_reward::<T>();
+		let elected_count = <Module<T>>::current_elected().len();
+		validators.into_iter().map(|(stash, old)| (stash, ValidatorPrefs {
+			unstake_threshold: old.unstake_threshold,
+			commission: commission_of::<T>(old.validator_payment, reward, elected_count),
+			blocked: false,
+		})).collect()
+	} else {
+		let validators = <deprecated::v3::Validators<T>>::enumerate().collect::<Vec<_>>();
+		for (stash, _) in &validators {
+			<deprecated::v3::Validators<T>>::remove(stash);
+		}
+		validators.into_iter().map(|(stash, old)| (stash, ValidatorPrefs {
+			unstake_threshold: old.unstake_threshold,
+			commission: old.commission,
+			blocked: false,
+		})).collect()
+	}
+}
+
+/// Migrate the preferences of the elected validators of the eras in history, like
+/// `migrate_validator_prefs`. The commission of a past era is computed from its actual reward.
+fn migrate_eras_validator_prefs<T: Trait>(version: u32) {
+	let current_era = <Module<T>>::current_era();
+	for era in current_era.saturating_sub(T::HistoryDepth::get())..=current_era {
+		let elected = <Module<T>>::eras_elected(&era);
+		let reward = if era == current_era {
+			estimated_era_reward::<T>()
+		} else {
+			<Module<T>>::eras_validator_reward(&era).unwrap_or_default()
+		};
+		for stash in &elected {
+			let prefs = if version < 3 {
+				if !<deprecated::v2::ErasValidatorPrefs<T>>::exists(&era, stash) {
+					continue;
+				}
+				let old = <deprecated::v2::ErasValidatorPrefs<T>>::get(&era, stash);
+				ValidatorPrefs {
+					unstake_threshold: old.unstake_threshold,
+					commission: commission_of::<T>(old.validator_payment, reward, elected.len()),
+					blocked: false,
+				}
+			} else {
+				if !<deprecated::v3::ErasValidatorPrefs<T>>::exists(&era, stash) {
+					continue;
+				}
+				let old = <deprecated::v3::ErasValidatorPrefs<T>>::get(&era, stash);
+				ValidatorPrefs {
+					unstake_threshold: old.unstake_threshold,
+					commission: old.commission,
+					blocked: false,
+				}
+			};
+			<ErasValidatorPrefs<T>>::insert(&era, stash, prefs);
+		}
+	}
+}
+
+/// Offline reports are now recorded per era in `OfflineRecords`. The slash counts of the
+/// validators are carried over to the current era. `SlashCount` can not be enumerated, so the
+/// ones of other stashes are left in storage, they are no longer read.
+fn migrate_offline_records<T: Trait>(validators: Vec<T::AccountId>) {
+	<deprecated::v4::RecentlyOffline<T>>::kill();
+
+	let current_era = <Module<T>>::current_era();
+	for stash in validators.into_iter().chain(<Module<T>>::current_elected()) {
+		let slash_count = <deprecated::v4::SlashCount<T>>::take(&stash);
+		if slash_count > 0 {
+			<OfflineRecords<T>>::insert(&current_era, &stash, slash_count);
+		}
+	}
+}
+
+/// The ledger of a controller in its layout before version 2, with no RING bonded as it could not
+/// be then.
+pub(crate) fn ledger_before_v2<T: Trait>(controller: &T::AccountId) -> Option<StakingLedgerOf<T>> {
+	<deprecated::v1::Ledger<T>>::get(controller).map(|old| StakingLedger {
+		stash: old.stash,
+		total: old.total,
+		active: old.active,
+		unlocking: old.unlocking,
+		total_ring: Zero::zero(),
+		active_ring: Zero::zero(),
+		ring_unlocking: vec![],
+	})
+}
+
+/// Ledgers now bond RING alongside KTON. The ledgers of `stashes` are rewritten in the new layout.
+/// The ones of the stashes which neither validate nor nominate can not be enumerated, they are
+/// read in their previous layout until next written, see `Module::ledger`.
+fn migrate_ledgers<T: Trait>(stashes: &[T::AccountId]) {
+	for stash in stashes {
+		if let Some(controller) = <Module<T>>::bonded(stash) {
+			if <Ledger<T>>::get(&controller).is_none() {
+				if let Some(ledger) = ledger_before_v2::<T>(&controller) {
+					<Ledger<T>>::insert(&controller, ledger);
+				}
+			}
+		}
+	}
+}
+
+/// `RewardDestination::StakedDeprecated` is rewritten as `Staked` for `stashes`. The payees of the
+/// stashes which neither validate nor nominate can not be enumerated, they are rewritten on their
+/// next payout, see `make_payout`.
+fn migrate_payees<T: Trait>(stashes: &[T::AccountId]) {
+	for stash in stashes {
+		if <Module<T>>::payee(stash) == RewardDestination::StakedDeprecated {
+			<Payee<T>>::insert(stash, RewardDestination::Staked);
+		}
+	}
+}
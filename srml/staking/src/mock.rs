@@ -2,12 +2,18 @@
 
 use std::{collections::HashSet, cell::RefCell};
 use primitives::{BuildStorage, Perbill};
-use primitives::traits::{IdentityLookup, Convert, OpaqueKeys, OnInitialize};
-use primitives::testing::{Header, UintAuthorityId};
+use primitives::traits::{IdentityLookup, Convert, OpaqueKeys, OnInitialize, Header as HeaderT};
+use primitives::generic::{Digest, DigestItem};
+use primitives::testing::{Header, UintAuthorityId, TestSignature, TestXt};
 use substrate_primitives::{H256, Blake2Hasher};
 use runtime_io;
 use srml_support::{impl_outer_origin, parameter_types, assert_ok, traits::{ Currency, Get}};
-use crate::{EraIndex, ErasNums, GenesisConfig, Module, Trait, StakerStatus, ValidatorPrefs, RewardDestination};
+use parity_codec::Encode;
+use crate::{
+    EraIndex, ErasNums, GenesisConfig, Module, Trait, StakerStatus, ValidatorPrefs, RewardDestination,
+    StakingBalance, Heartbeat, GrandpaVote,
+    heartbeat::{signed_payload, HEARTBEAT_TAG},
+};
 
 
 /// The AccountId alias in this test module.
@@ -28,6 +34,7 @@ impl Convert<u128, u64> for CurrencyToVoteHandler {
 
 thread_local! {
 	static SESSION: RefCell<(Vec<AccountId>, HashSet<AccountId>)> = RefCell::new(Default::default());
+	static SLASH_DEFER_DURATION: RefCell<EraIndex> = RefCell::new(0);
 }
 
 pub struct TestSessionHandler;
@@ -52,6 +59,22 @@ pub fn is_disabled(validator: AccountId) -> bool {
     SESSION.with(|d| d.borrow().1.contains(&validator))
 }
 
+/// The slash defer duration of the test, set by `ExtBuilder::slash_defer_duration`.
+pub struct SlashDeferDuration;
+impl Get<EraIndex> for SlashDeferDuration {
+    fn get() -> EraIndex {
+        SLASH_DEFER_DURATION.with(|v| *v.borrow())
+    }
+}
+
+/// The authority keys of the session validators are their account ids.
+pub struct TestAuthorities;
+impl Get<Vec<UintAuthorityId>> for TestAuthorities {
+    fn get() -> Vec<UintAuthorityId> {
+        SESSION.with(|x| x.borrow().0.iter().map(|v| UintAuthorityId(*v)).collect())
+    }
+}
+
 impl_outer_origin!{
 	pub enum Origin for Test {}
 }
@@ -86,7 +109,9 @@ impl kton::Trait for Test {
     type Event = ();
     type OnMinted = ();
     type OnRemoval = ();
+    type OnFreeBalanceZero = Staking;
     type SystemRefund = ();
+    type KtonIssuanceCurve = kton::MonthlyCompoundCurve;
 }
 
 parameter_types! {
@@ -109,27 +134,57 @@ parameter_types! {
 	pub const SessionsPerEra: session::SessionIndex = 3;
 	pub const BondingDuration: EraIndex = 3;
 	pub const ErasPerEpoch: ErasNums = 10;
+	pub const HistoryDepth: EraIndex = 10;
+	pub const RewardDepositMonths: u32 = 12;
+	pub const SlashRewardFraction: Perbill = Perbill::from_percent(10);
+	pub const RingKtonRatio: u32 = 10;
+	pub const MaxNominatorRewardedPerValidator: u32 = 4;
+	pub const OfflineRecordsDepth: EraIndex = 5;
 }
 
 parameter_types! {
 	// decimal 3
 	pub const CAP: Balance = 10_000_000_000_000;
+	pub const StakersRatio: Perbill = Perbill::from_percent(60);
+	pub const KtonHoldersRatio: Perbill = Perbill::from_percent(20);
+	pub const TreasuryAccount: AccountId = 1000;
 }
 
 
 impl Trait for Test {
     type Currency = Kton;
     type RewardCurrency = Ring;
+    type RingKtonRatio = RingKtonRatio;
     type CurrencyToVote = CurrencyToVoteHandler;
     type OnRewardMinted = ();
     type Event = ();
     type Slash = ();
+    type RingSlash = ();
     type Reward = ();
     type SessionsPerEra = SessionsPerEra;
+    type SessionLength = Period;
     type BondingDuration = BondingDuration;
     // customed
     type Cap = CAP;
     type ErasPerEpoch = ErasPerEpoch;
+    type StakersRatio = StakersRatio;
+    type KtonHoldersRatio = KtonHoldersRatio;
+    type TreasuryAccount = TreasuryAccount;
+    type HistoryDepth = HistoryDepth;
+    type RewardDepositMonths = RewardDepositMonths;
+    type SlashDeferDuration = SlashDeferDuration;
+    type SlashCancelOrigin = system::EnsureRoot<AccountId>;
+    type SlashRewardFraction = SlashRewardFraction;
+    type MaxNominatorRewardedPerValidator = MaxNominatorRewardedPerValidator;
+    type OfflineRecordsDepth = OfflineRecordsDepth;
+    type AuthorityId = UintAuthorityId;
+    type AuthoritySignature = TestSignature;
+    type Authorities = TestAuthorities;
+    type GrandpaAuthorityId = UintAuthorityId;
+    type GrandpaSignature = TestSignature;
+    type GrandpaAuthorities = TestAuthorities;
+    type Call = crate::Call<Test>;
+    type UncheckedExtrinsic = TestXt<crate::Call<Test>>;
 }
 
 pub struct ExtBuilder {
@@ -141,6 +196,7 @@ pub struct ExtBuilder {
     validator_count: u32,
     minimum_validator_count: u32,
     fair: bool,
+    slash_defer_duration: EraIndex,
 }
 
 impl Default for ExtBuilder {
@@ -153,7 +209,8 @@ impl Default for ExtBuilder {
             nominate: true,
             validator_count: 2,
             minimum_validator_count: 0,
-            fair: true
+            fair: true,
+            slash_defer_duration: 0,
         }
     }
 }
@@ -187,7 +244,12 @@ impl ExtBuilder {
         self.fair = is_fair;
         self
     }
+    pub fn slash_defer_duration(mut self, eras: EraIndex) -> Self {
+        self.slash_defer_duration = eras;
+        self
+    }
     pub fn build(self) -> runtime_io::TestExternalities<Blake2Hasher> {
+        SLASH_DEFER_DURATION.with(|v| *v.borrow_mut() = self.slash_defer_duration);
         let (mut t, mut c) = system::GenesisConfig::<Test>::default().build_storage().unwrap();
         let balance_factor = if self.existential_deposit > 0 {
             256
@@ -242,6 +304,7 @@ impl ExtBuilder {
                 (101, 2000 * balance_factor),
             ],
             sys_acc: 42,
+            existential_deposit: self.existential_deposit,
             vesting: vec![],
         }.assimilate_storage(&mut t, &mut c);
 
@@ -311,22 +374,44 @@ pub fn assert_total_expo(acc: u64, val: u64) {
 pub fn bond_validator(acc: u64, val: u64) {
     // a = controller
     // a + 1 = stash
+    // The stake is bonded in KTON, the stash is funded in both currencies
     let _ = Ring::make_free_balance_be(&(acc+1), val);
-    assert_ok!(Staking::bond(Origin::signed(acc+1), acc, val, RewardDestination::Controller));
+    let _ = Kton::make_free_balance_be(&(acc+1), val);
+    assert_ok!(Staking::bond(Origin::signed(acc+1), acc, StakingBalance::Kton(val), RewardDestination::Controller));
     assert_ok!(Staking::validate(Origin::signed(acc), ValidatorPrefs::default()));
 }
 
 pub fn bond_nominator(acc: u64, val: u64, target: Vec<u64>) {
     // a = controller
     // a + 1 = stash
+    // The stake is bonded in KTON, the stash is funded in both currencies
     let _ = Ring::make_free_balance_be(&(acc+1), val);
-    assert_ok!(Staking::bond(Origin::signed(acc+1), acc, val, RewardDestination::Controller));
+    let _ = Kton::make_free_balance_be(&(acc+1), val);
+    assert_ok!(Staking::bond(Origin::signed(acc+1), acc, StakingBalance::Kton(val), RewardDestination::Controller));
     assert_ok!(Staking::nominate(Origin::signed(acc), target));
 }
 
+/// Send the heartbeats of the session validators in the current session, the ones already sent
+/// are skipped.
+pub fn send_heartbeats() {
+    let session_index = Session::current_index();
+    for (authority_index, validator) in Session::validators().into_iter().enumerate() {
+        let heartbeat = Heartbeat {
+            block_number: System::block_number(),
+            session_index,
+            authority_index: authority_index as u32,
+        };
+        let signature = TestSignature(validator, signed_payload(HEARTBEAT_TAG, &heartbeat));
+        let _ = Staking::heartbeat(Origin::NONE, heartbeat, signature);
+    }
+}
+
+/// Start the sessions up to `session_index`. All the validators are alive in each session, see
+/// `send_heartbeats`.
 pub fn start_session(session_index: session::SessionIndex) {
     for i in 0..(session_index - Session::current_index()) {
         System::set_block_number((i + 1).into());
+        send_heartbeats();
         Session::on_initialize(System::block_number());
     }
     assert_eq!(Session::current_index(), session_index);
@@ -336,3 +421,46 @@ pub fn start_era(era_index: EraIndex) {
     start_session((era_index * 3).into());
     assert_eq!(Staking::current_era(), era_index);
 }
+
+/// A block `number` of the Aura `slot`, sealed by `author`.
+pub fn aura_block(slot: u64, number: BlockNumber, author: AccountId) -> Header {
+    let mut header = Header {
+        parent_hash: Default::default(),
+        number,
+        state_root: Default::default(),
+        extrinsics_root: Default::default(),
+        digest: Digest { logs: vec![DigestItem::PreRuntime(*b"aura", slot.encode())] },
+    };
+    let seal = TestSignature(author, header.hash().as_ref().to_vec());
+    header.digest.push(DigestItem::Seal(*b"aura", seal.encode()));
+    header
+}
+
+/// A GRANDPA `vote` cast in `round` of the authority set `set_id`, signed by `voter`.
+pub fn grandpa_vote(
+    vote: GrandpaVote<H256, BlockNumber>,
+    round: u64,
+    set_id: u64,
+    voter: AccountId,
+) -> (GrandpaVote<H256, BlockNumber>, TestSignature) {
+    let signature = TestSignature(voter, (&vote, round, set_id).encode());
+    (vote, signature)
+}
+
+/// Write the preferences of the validators back in their layout before version 3, with no
+/// payment, to migrate the storage from an older version.
+pub fn write_validator_prefs_before_v3() {
+    use srml_support::{StorageMap, EnumerableStorageMap};
+    use crate::{migration::deprecated::v2, Validators};
+
+    let validators = <Validators<Test>>::enumerate().collect::<Vec<_>>();
+    for (stash, _) in &validators {
+        <Validators<Test>>::remove(stash);
+    }
+    for (stash, prefs) in validators.iter().rev() {
+        <v2::Validators<Test>>::insert(stash, v2::ValidatorPrefs {
+            unstake_threshold: prefs.unstake_threshold,
+            validator_payment: 0,
+        });
+    }
+}
@@ -0,0 +1,258 @@
+//! Reporting of offences committed by validators.
+//!
+//! Offences are reported through `ReportOffence`: unresponsiveness by the heartbeats, at the end
+//! of each session, and equivocations by anyone submitting an `EquivocationProof` with
+//! `report_equivocation`. Equivocations are proven with the messages the validators sign for
+//! consensus: the seals of two Aura blocks, or two GRANDPA votes.
+//!
+//! Each kind of equivocation has its own slash fraction, which grows with the number of validators
+//! committing it in the same session. Unresponsive validators are counted offline instead, and
+//! only slashed once they exceed their grace and unstake threshold. An offender is only reported
+//! once per kind and session.
+
+use super::*;
+use primitives::{ConsensusEngineId, generic::DigestItem};
+
+/// The kind of an offence.
+#[derive(PartialEq, Eq, Clone, Copy, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum OffenceKind {
+	/// Signing two different GRANDPA votes in the same round.
+	GrandpaEquivocation,
+	/// Authoring two different blocks in the same Aura slot.
+	AuraEquivocation,
+	/// Not sending a heartbeat for a whole session. Unresponsive validators are counted offline,
+	/// see `Module::note_offline`.
+	Unresponsiveness,
+}
+
+impl OffenceKind {
+	/// The fraction of the exposure which is slashed when `offenders_count` validators out of
+	/// `validator_set_count` committed this offence in the same session.
+	///
+	/// Unresponsiveness is not slashed by a fraction, but by the offline slash once the offender
+	/// exceeds its grace and unstake threshold.
+	pub fn slash_fraction(&self, offenders_count: u32, validator_set_count: u32) -> Perbill {
+		let k = offenders_count as u64;
+		let n = (validator_set_count as u64).max(1);
+		match self {
+			// min((3k / n)^2, 1)
+			OffenceKind::GrandpaEquivocation | OffenceKind::AuraEquivocation => {
+				let x = (3 * k).min(n);
+				Perbill::from_rational_approximation(x * x, n * n)
+			},
+			OffenceKind::Unresponsiveness => Perbill::zero(),
+		}
+	}
+}
+
+/// An offence committed by some validators in a session.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Offence<AccountId> {
+	/// The kind of the offence.
+	pub kind: OffenceKind,
+	/// The session in which the offence was committed.
+	pub session_index: SessionIndex,
+	/// The size of the validator set in that session.
+	pub validator_set_count: u32,
+	/// The stash accounts of the offenders.
+	pub offenders: Vec<AccountId>,
+}
+
+/// The engine id of Aura, in the digests of the blocks.
+const AURA_ENGINE_ID: ConsensusEngineId = *b"aura";
+
+/// A GRANDPA vote for a block, encoded as the GRANDPA message it is signed in.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum GrandpaVote<Hash, BlockNumber> {
+	/// A prevote for the block with the hash and number.
+	Prevote(Hash, BlockNumber),
+	/// A precommit for the block with the hash and number.
+	Precommit(Hash, BlockNumber),
+}
+
+impl<Hash, BlockNumber> GrandpaVote<Hash, BlockNumber> {
+	fn is_prevote(&self) -> bool {
+		match self {
+			GrandpaVote::Prevote(..) => true,
+			GrandpaVote::Precommit(..) => false,
+		}
+	}
+}
+
+/// A proof that a validator equivocated, made of the two conflicting messages it signed.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum EquivocationProof<Header, Hash, BlockNumber, GrandpaSignature> {
+	/// Two blocks authored in the same Aura slot, each sealed by the author of the slot.
+	Aura(Header, Header),
+	/// Two votes of the same kind cast in the same GRANDPA round, each signed with the GRANDPA
+	/// key of the voter over `(vote, round, set_id)`.
+	Grandpa {
+		/// The round the votes were cast in.
+		round: u64,
+		/// The authority set the votes were cast in.
+		set_id: u64,
+		/// The index of the voter in the GRANDPA authorities of the current session.
+		authority_index: u32,
+		/// The first vote, with its signature.
+		first: (GrandpaVote<Hash, BlockNumber>, GrandpaSignature),
+		/// The second vote, with its signature.
+		second: (GrandpaVote<Hash, BlockNumber>, GrandpaSignature),
+	},
+}
+
+/// An equivocation proven by an `EquivocationProof`: its kind, the stash of the offender, and
+/// the Aura slot, or the GRANDPA round and set, it was committed in.
+pub type Equivocation<AccountId> = (OffenceKind, AccountId, u64, u64);
+
+/// The session keys of the current session, each with its session validator, a controller.
+///
+/// `keys` must be in the order of the session validators. They are not used if they are not as
+/// many, as they can not be told apart then.
+pub(crate) fn session_keys<T: Trait, K>(keys: Vec<K>)
+	-> result::Result<Vec<(K, T::AccountId)>, &'static str>
+{
+	let validators = <session::Module<T>>::validators();
+	ensure!(keys.len() == validators.len(), "authorities are not the session validators");
+
+	Ok(keys.into_iter().zip(validators).collect())
+}
+
+/// The authorities of the current session, each with its session validator.
+pub(crate) fn session_authorities<T: Trait>()
+	-> result::Result<Vec<(T::AuthorityId, T::AccountId)>, &'static str>
+{
+	session_keys::<T, _>(T::Authorities::get())
+}
+
+/// The slot of an Aura block, from its pre-runtime digest.
+fn aura_slot<T: Trait>(header: &T::Header) -> result::Result<u64, &'static str> {
+	header.digest()
+		.logs()
+		.iter()
+		.filter_map(|log| match log {
+			DigestItem::PreRuntime(id, data) if *id == AURA_ENGINE_ID => u64::decode(&mut &data[..]),
+			_ => None,
+		})
+		.next()
+		.ok_or("block has no slot")
+}
+
+/// Check the seal of an Aura block against its `author`, returning the hash of the block before
+/// it was sealed, the one the seal signs.
+fn check_aura_seal<T: Trait>(
+	header: &T::Header,
+	author: &T::AuthorityId,
+) -> result::Result<T::Hash, &'static str> {
+	let mut header = header.clone();
+	let signature = match header.digest_mut().pop() {
+		Some(DigestItem::Seal(id, data)) if id == AURA_ENGINE_ID =>
+			T::AuthoritySignature::decode(&mut &data[..]).ok_or("invalid seal")?,
+		_ => return Err("block is not sealed"),
+	};
+	let pre_hash = header.hash();
+	ensure!(signature.verify(pre_hash.as_ref(), author), "bad signature");
+
+	Ok(pre_hash)
+}
+
+/// Check an equivocation proof against the authorities of the current session.
+///
+/// The authorities of past sessions are not kept, so an equivocation can only be reported while
+/// its offender is still an authority. It is reported in the current session.
+pub fn check_equivocation_proof<T: Trait>(
+	proof: &EquivocationProofOf<T>,
+) -> result::Result<Equivocation<T::AccountId>, &'static str> {
+	let (kind, controller, round, set_id) = match proof {
+		EquivocationProof::Aura(first, second) => {
+			let slot = aura_slot::<T>(first)?;
+			ensure!(aura_slot::<T>(second)? == slot, "blocks are not in the same slot");
+
+			let authorities = session_authorities::<T>()?;
+			ensure!(!authorities.is_empty(), "no authorities");
+			let (author, controller) = &authorities[(slot % authorities.len() as u64) as usize];
+			ensure!(
+				check_aura_seal::<T>(first, author)? != check_aura_seal::<T>(second, author)?,
+				"blocks are the same"
+			);
+
+			(OffenceKind::AuraEquivocation, controller.clone(), slot, 0)
+		},
+		EquivocationProof::Grandpa { round, set_id, authority_index, first, second } => {
+			ensure!(first.0.is_prevote() == second.0.is_prevote(), "votes are not of the same kind");
+			ensure!(first.0 != second.0, "votes are the same");
+
+			let (voter, controller) = session_keys::<T, _>(T::GrandpaAuthorities::get())?
+				.into_iter()
+				.nth(*authority_index as usize)
+				.ok_or("unknown authority")?;
+			for (vote, signature) in &[first, second] {
+				ensure!(signature.verify(&(vote, round, set_id).encode()[..], &voter), "bad signature");
+			}
+
+			(OffenceKind::GrandpaEquivocation, controller, *round, *set_id)
+		},
+	};
+
+	let offender = <Module<T>>::ledger(&controller)
+		.map(|ledger| ledger.stash)
+		.ok_or("offender is not a validator")?;
+
+	Ok((kind, offender, round, set_id))
+}
+
+/// Something that can report offences.
+pub trait ReportOffence<AccountId> {
+	/// Report an offence. The reporters share a part of the slash.
+	fn report_offence(reporters: Vec<AccountId>, offence: Offence<AccountId>) -> result::Result<(), &'static str>;
+}
+
+impl<T: Trait> ReportOffence<T::AccountId> for Module<T> {
+	fn report_offence(
+		reporters: Vec<T::AccountId>,
+		offence: Offence<T::AccountId>,
+	) -> result::Result<(), &'static str> {
+		let era = <Module<T>>::era_of_session(offence.session_index)
+			.ok_or("offence is out of history")?;
+		let key = (offence.kind, offence.session_index);
+
+		// De-duplicate the offenders against the previous reports of the session.
+		let mut offenders = <OffenceReports<T>>::get(&era, &key);
+		let mut new_offenders = Vec::new();
+		for offender in offence.offenders {
+			if !offenders.contains(&offender) && !new_offenders.contains(&offender) {
+				new_offenders.push(offender);
+			}
+		}
+		ensure!(!new_offenders.is_empty(), "offence already reported");
+		offenders.extend(new_offenders.iter().cloned());
+		<OffenceReports<T>>::insert(&era, &key, &offenders);
+
+		for offender in &new_offenders {
+			<Module<T>>::deposit_event(RawEvent::Offence(offence.kind, offence.session_index, offender.clone()));
+		}
+
+		// Unresponsive validators are counted offline in the era of the session.
+		if offence.kind == OffenceKind::Unresponsiveness {
+			for offender in &new_offenders {
+				<Module<T>>::note_offline(era, offender, 1);
+			}
+			return Ok(());
+		}
+
+		// The slash fraction grows with the number of offenders, so the previous offenders of the
+		// session are slashed again. Slashes do not stack within a slashing span, only the
+		// difference is applied.
+		let invulnerables = <Module<T>>::invulnerables();
+		let slash_fraction = offence.kind.slash_fraction(offenders.len() as u32, offence.validator_set_count);
+		for offender in offenders.iter().filter(|o| !invulnerables.contains(o)) {
+			let slash = slash_fraction * <Module<T>>::eras_stakers(&era, offender).total;
+			<Module<T>>::slash_validator(era, offender, slash, reporters.clone());
+		}
+
+		Ok(())
+	}
+}
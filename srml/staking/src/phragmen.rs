@@ -19,7 +19,7 @@
 use rstd::{prelude::*, collections::btree_map::BTreeMap};
 use primitives::{PerU128};
 use primitives::traits::{Zero, Convert, Saturating};
-use crate::{BalanceOf, RewardBalanceOf, Assignment, RawAssignment, ExpoMap, Trait, ValidatorPrefs};
+use crate::{BalanceOf, Assignment, RawAssignment, ExpoMap, Trait, ValidatorPrefs};
 
 type Fraction = PerU128;
 /// Wrapper around the type used as the _safe_ wrapper around a `balance`.
@@ -92,7 +92,7 @@ pub fn elect<T: Trait + 'static, FV, FN, FS>(
 	nominator_iter: FN,
 	stash_of: FS,
 ) -> Option<(Vec<T::AccountId>, Vec<(T::AccountId, Vec<RawAssignment<T>>)>)> where
-	FV: Iterator<Item=(T::AccountId, ValidatorPrefs<RewardBalanceOf<T>>)>,
+	FV: Iterator<Item=(T::AccountId, ValidatorPrefs)>,
 	FN: Iterator<Item=(T::AccountId, Vec<T::AccountId>)>,
 	for <'r> FS: Fn(&'r T::AccountId) -> BalanceOf<T>,
 {
@@ -0,0 +1,262 @@
+//! Slashing of validators and their nominators.
+//!
+//! A slash is not applied when the offence is reported. It is recorded as an `UnappliedSlash`
+//! which is applied `SlashDeferDuration` eras later, unless it is cancelled in the meantime by
+//! `cancel_deferred_slash`.
+//!
+//! The eras of each staker are divided into slashing spans. A span ends when the staker is
+//! slashed for an offence committed in it, the next one starting at the next era. Slashes for
+//! offences within the same span do not stack: a staker only loses the highest of them, so a new
+//! slash only applies the part exceeding what was already slashed in that span. Offences committed
+//! before a staker could react to its first slash are thus only punished once, while the offences
+//! of later spans are slashed independently. A cancelled slash is taken back from its span.
+//!
+//! Slashes are computed on the voting power of the stakers. When applied, they are split between
+//! the bonded RING and KTON of a staker pro-rata to the power of each.
+
+use super::*;
+
+/// The index of a slashing span of a staker.
+pub type SpanIndex = u32;
+
+/// The slashing spans of a staker, the current one and the previous ones still kept.
+///
+/// Only the spans which ended within the last `HistoryDepth` eras are kept, as older offences can
+/// not be reported.
+#[derive(PartialEq, Eq, Clone, Default, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct SlashingSpans {
+	/// The index of the current span.
+	pub span_index: SpanIndex,
+	/// The era the current span started at.
+	pub last_start: EraIndex,
+	/// The eras the previous spans started at, the most recent first.
+	pub prior: Vec<EraIndex>,
+}
+
+impl SlashingSpans {
+	/// The span of an offence committed in `era`, if it is still kept.
+	pub fn span_of(&self, era: EraIndex) -> Option<SpanIndex> {
+		if era >= self.last_start {
+			return Some(self.span_index);
+		}
+		self.prior
+			.iter()
+			.position(|start| era >= *start)
+			.map(|i| self.span_index - 1 - i as SpanIndex)
+	}
+
+	/// End the current span, the next one starting at `next_start`, and forget the spans which
+	/// ended before `oldest_era`. Returns the indices of the forgotten spans.
+	fn end_span(&mut self, next_start: EraIndex, oldest_era: EraIndex) -> Vec<SpanIndex> {
+		self.prior.insert(0, self.last_start);
+		self.last_start = next_start;
+		self.span_index += 1;
+
+		// Each previous span ended when the next one started.
+		let kept = rstd::iter::once(self.last_start)
+			.chain(self.prior.iter().cloned())
+			.take(self.prior.len())
+			.take_while(|end| *end > oldest_era)
+			.count();
+		let pruned = (kept..self.prior.len())
+			.map(|i| self.span_index - 1 - i as SpanIndex)
+			.collect();
+		self.prior.truncate(kept);
+
+		pruned
+	}
+}
+
+/// A pending slash record. The value of the slash has been computed but not applied yet.
+#[derive(PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct UnappliedSlash<AccountId, Balance: HasCompact> {
+	/// The era the offence was committed in.
+	pub era: EraIndex,
+	/// The stash ID of the offending validator.
+	pub validator: AccountId,
+	/// The validator's own slash.
+	pub own: Balance,
+	/// All other slashed stakers and amounts.
+	pub others: Vec<(AccountId, Balance)>,
+	/// Reporters of the offence, they share `SlashRewardFraction` of the slash.
+	pub reporters: Vec<AccountId>,
+}
+
+/// Record a slash of `amount` of a staker for an offence committed in `era`, returning the part
+/// of it exceeding the previous slashes of the same span. The current span of the staker ends if
+/// the offence is in it.
+fn slash_in_span<T: Trait>(
+	era: EraIndex,
+	stash: &T::AccountId,
+	amount: BalanceOf<T>,
+) -> BalanceOf<T> {
+	let mut spans = <Module<T>>::slashing_spans(stash);
+	let span = match spans.span_of(era) {
+		Some(span) => span,
+		None => return Zero::zero(),
+	};
+	let prior = <SpanSlash<T>>::get(stash, &span);
+	if amount <= prior {
+		return Zero::zero();
+	}
+	<SpanSlash<T>>::insert(stash, &span, amount);
+
+	if span == spans.span_index {
+		let current_era = <Module<T>>::current_era();
+		let oldest_era = current_era.saturating_sub(T::HistoryDepth::get());
+		for pruned in spans.end_span(current_era + 1, oldest_era) {
+			<SpanSlash<T>>::remove(stash, &pruned);
+		}
+		<SlashingSpans<T>>::insert(stash, &spans);
+	}
+
+	amount - prior
+}
+
+/// Compute the slash of a validator and its nominators, based on their exposure in the era of
+/// the offence. The validator's stash is slashed by preference, the rest is taken from its
+/// nominators pro-rata to their exposure.
+///
+/// Returns `None` if nothing is to be slashed on top of the previous slashes of the spans.
+pub fn compute_slash<T: Trait>(
+	era: EraIndex,
+	stash: &T::AccountId,
+	slash: BalanceOf<T>,
+	reporters: Vec<T::AccountId>,
+) -> Option<UnappliedSlash<T::AccountId, BalanceOf<T>>> {
+	// The exposure (backing stake) information of the validator to be slashed.
+	let exposure = <Module<T>>::eras_stakers(&era, stash);
+	// The amount we are actually going to slash (can't be bigger than the validator's total
+	// exposure)
+	let slash = slash.min(exposure.total);
+	// The amount we'll slash from the validator's stash directly.
+	let own_slash = slash_in_span::<T>(era, stash, exposure.own.min(slash));
+
+	// The amount remaining that we can't slash from the validator, that must be taken from the
+	// nominators.
+	let rest_slash = slash - exposure.own.min(slash);
+	let mut others = Vec::new();
+	if !rest_slash.is_zero() {
+		// The total to be slashed from the nominators.
+		let total = exposure.total - exposure.own;
+		if !total.is_zero() {
+			for i in exposure.others.iter() {
+				let per_u64 = Perbill::from_rational_approximation(i.value, total);
+				let nominator_slash = slash_in_span::<T>(era, &i.who, per_u64 * rest_slash);
+				if !nominator_slash.is_zero() {
+					others.push((i.who.clone(), nominator_slash));
+				}
+			}
+		}
+	}
+
+	if own_slash.is_zero() && others.is_empty() {
+		None
+	} else {
+		Some(UnappliedSlash { era, validator: stash.clone(), own: own_slash, others, reporters })
+	}
+}
+
+/// Take a cancelled slash back from the spans of its stakers, so that it does not reduce a later
+/// slash of the same span. The spans it ended are not resumed.
+pub fn cancel_slash<T: Trait>(unapplied_slash: &UnappliedSlash<T::AccountId, BalanceOf<T>>) {
+	let stakers = rstd::iter::once((&unapplied_slash.validator, unapplied_slash.own))
+		.chain(unapplied_slash.others.iter().map(|(who, value)| (who, *value)));
+	for (stash, value) in stakers {
+		if let Some(span) = <Module<T>>::slashing_spans(stash).span_of(unapplied_slash.era) {
+			<SpanSlash<T>>::mutate(stash, &span, |slashed| *slashed = slashed.saturating_sub(value));
+		}
+	}
+}
+
+/// Apply a previously-unapplied slash.
+pub fn apply_slash<T: Trait>(unapplied_slash: UnappliedSlash<T::AccountId, BalanceOf<T>>) {
+	let mut ring_imbalance = <RingNegativeImbalanceOf<T>>::zero();
+	let mut kton_imbalance = <NegativeImbalanceOf<T>>::zero();
+
+	do_slash::<T>(
+		&unapplied_slash.validator,
+		unapplied_slash.own,
+		&mut ring_imbalance,
+		&mut kton_imbalance,
+	);
+	for &(ref nominator, nominator_slash) in &unapplied_slash.others {
+		do_slash::<T>(nominator, nominator_slash, &mut ring_imbalance, &mut kton_imbalance);
+	}
+
+	pay_reporters::<T, T::RewardCurrency, T::RingSlash>(ring_imbalance, &unapplied_slash.reporters);
+	pay_reporters::<T, T::Currency, T::Slash>(kton_imbalance, &unapplied_slash.reporters);
+}
+
+/// Give `SlashRewardFraction` of the slashed funds to the reporters, split evenly between them.
+/// The rest goes to `S`.
+fn pay_reporters<T, C, S>(slashed_imbalance: C::NegativeImbalance, reporters: &[T::AccountId])
+where
+	T: Trait,
+	C: Currency<T::AccountId>,
+	S: OnUnbalanced<C::NegativeImbalance>,
+{
+	if reporters.is_empty() {
+		S::on_unbalanced(slashed_imbalance);
+		return;
+	}
+
+	let reward_payout = T::SlashRewardFraction::get() * slashed_imbalance.peek();
+	let (mut reward_payout, rest) = slashed_imbalance.split(reward_payout);
+
+	let per_reporter = reward_payout.peek() / (reporters.len() as u32).into();
+	for reporter in reporters {
+		let (reporter_reward, rest) = reward_payout.split(per_reporter);
+		reward_payout = rest;
+
+		// Best effort - not much that can be done on fail.
+		let _ = C::resolve_creating(reporter, reporter_reward);
+	}
+
+	// The rest goes to the treatment of slashes, including the rounding remainder.
+	S::on_unbalanced(reward_payout.merge(rest));
+}
+
+/// Slash the bonded funds of a stash, including the ones being unbonded. `value` is the voting
+/// power to slash.
+///
+/// Only bonded funds are slashed, best effort.
+fn do_slash<T: Trait>(
+	stash: &T::AccountId,
+	value: BalanceOf<T>,
+	ring_imbalance: &mut RingNegativeImbalanceOf<T>,
+	kton_imbalance: &mut NegativeImbalanceOf<T>,
+) {
+	let controller = match <Module<T>>::bonded(stash) {
+		Some(controller) => controller,
+		None => return,
+	};
+	let mut ledger = match <Module<T>>::ledger(&controller) {
+		Some(ledger) => ledger,
+		None => return,
+	};
+
+	// The same part of the bonded RING and KTON is slashed.
+	let power = <Module<T>>::slashable_balance_of(stash);
+	if power.is_zero() {
+		return;
+	}
+	let slash_ratio = Perbill::from_rational_approximation(value.min(power), power);
+
+	let (ring_value, kton_value) = ledger.slash(
+		slash_ratio * ledger.total_ring,
+		slash_ratio * ledger.total,
+		T::RewardCurrency::minimum_balance(),
+		T::Currency::minimum_balance(),
+	);
+	if !ring_value.is_zero() || !kton_value.is_zero() {
+		let (ring_slashed, _) = T::RewardCurrency::slash(stash, ring_value);
+		let (kton_slashed, _) = T::Currency::slash(stash, kton_value);
+		<Module<T>>::update_ledger(&controller, &ledger);
+		<Module<T>>::deposit_event(RawEvent::Slash(stash.clone(), ring_slashed.peek(), kton_slashed.peek()));
+		ring_imbalance.subsume(ring_slashed);
+		kton_imbalance.subsume(kton_slashed);
+	}
+}
@@ -1,9 +1,11 @@
 use mock::*;
 use phragmen;
+use primitives::testing::TestSignature;
 use primitives::traits::OnInitialize;
+use substrate_primitives::H256;
 use runtime_io::with_externalities;
 use srml_support::{assert_eq_uvec, assert_noop, assert_ok, EnumerableStorageMap};
-use srml_support::traits::{Currency, ReservableCurrency};
+use srml_support::traits::{Currency, LockableCurrency, ReservableCurrency, WithdrawReasons};
 
 use super::*;
 
@@ -17,21 +19,21 @@ fn basic_setup_works() {
         assert_eq!(Staking::bonded(&1), None);        // Account 1 is not a stashed
 
         // Account 10 controls the stash from account 11, which is 100 * balance_factor units
-        assert_eq!(Staking::ledger(&10), Some(StakingLedger { stash: 11, total: 1000, active: 1000, unlocking: vec![] }));
+        assert_eq!(Staking::ledger(&10), Some(StakingLedger { stash: 11, total: 1000, active: 1000, unlocking: vec![], ..Default::default() }));
         // Account 20 controls the stash from account 21, which is 200 * balance_factor units
-        assert_eq!(Staking::ledger(&20), Some(StakingLedger { stash: 21, total: 1000, active: 1000, unlocking: vec![] }));
+        assert_eq!(Staking::ledger(&20), Some(StakingLedger { stash: 21, total: 1000, active: 1000, unlocking: vec![], ..Default::default() }));
         // Account 1 does not control any stash
         assert_eq!(Staking::ledger(&1), None);
 
         // ValidatorPrefs are default, thus unstake_threshold is 3, other values are default for their type
         assert_eq!(<Validators<Test>>::enumerate().collect::<Vec<_>>(), vec![
-            (31, ValidatorPrefs { unstake_threshold: 3, validator_payment: 0 }),
-            (21, ValidatorPrefs { unstake_threshold: 3, validator_payment: 0 }),
-            (11, ValidatorPrefs { unstake_threshold: 3, validator_payment: 0 })
+            (31, ValidatorPrefs { unstake_threshold: 3, commission: Perbill::zero(), blocked: false }),
+            (21, ValidatorPrefs { unstake_threshold: 3, commission: Perbill::zero(), blocked: false }),
+            (11, ValidatorPrefs { unstake_threshold: 3, commission: Perbill::zero(), blocked: false })
         ]);
 
         // Account 100 is the default nominator
-        assert_eq!(Staking::ledger(100), Some(StakingLedger { stash: 101, total: 500, active: 500, unlocking: vec![] }));
+        assert_eq!(Staking::ledger(100), Some(StakingLedger { stash: 101, total: 500, active: 500, unlocking: vec![], ..Default::default() }));
         assert_eq!(Staking::nominators(101), vec![11, 21]);
 
         // Account 10 is exposed by 1000 * balance_factor from their own stash in account 11 + the default nominator vote
@@ -60,3 +62,1268 @@ fn basic_setup_works() {
         check_exposure_all();
     });
 }
+
+#[test]
+fn rebond_works() {
+    // Rebonding takes back funds from the unlocking chunks
+    with_externalities(&mut ExtBuilder::default()
+        .nominate(false)
+        .build(), || {
+        start_era(2);
+
+        // Nothing to rebond yet
+        assert_noop!(Staking::rebond(Origin::signed(10), StakingBalance::Kton(500)), "no unlocking chunk");
+
+        // Unbond almost everything
+        assert_ok!(Staking::unbond(Origin::signed(10), StakingBalance::Kton(900)));
+        assert_eq!(Staking::ledger(&10), Some(StakingLedger {
+            stash: 11,
+            total: 1000,
+            active: 100,
+            unlocking: vec![UnlockChunk { value: 900, era: 2 + 3 }],
+            ..Default::default()
+        }));
+
+        // Rebond part of it
+        assert_ok!(Staking::rebond(Origin::signed(10), StakingBalance::Kton(500)));
+        assert_eq!(Staking::ledger(&10), Some(StakingLedger {
+            stash: 11,
+            total: 1000,
+            active: 600,
+            unlocking: vec![UnlockChunk { value: 400, era: 2 + 3 }],
+            ..Default::default()
+        }));
+
+        // Rebonding more than unlocking only rebonds what is unlocking
+        assert_ok!(Staking::rebond(Origin::signed(10), StakingBalance::Kton(1000)));
+        assert_eq!(Staking::ledger(&10), Some(StakingLedger {
+            stash: 11,
+            total: 1000,
+            active: 1000,
+            unlocking: vec![],
+            ..Default::default()
+        }));
+
+        // Only a controller can rebond
+        assert_noop!(Staking::rebond(Origin::signed(11), StakingBalance::Kton(100)), "not a controller");
+    });
+}
+
+#[test]
+fn rebond_takes_most_recent_chunks_first() {
+    // A chunk is split when only part of it is rebonded
+    with_externalities(&mut ExtBuilder::default()
+        .nominate(false)
+        .build(), || {
+        start_era(1);
+        assert_ok!(Staking::unbond(Origin::signed(10), StakingBalance::Kton(100)));
+        start_era(2);
+        assert_ok!(Staking::unbond(Origin::signed(10), StakingBalance::Kton(200)));
+        assert_ok!(Staking::unbond(Origin::signed(10), StakingBalance::Kton(300)));
+        assert_eq!(Staking::ledger(&10), Some(StakingLedger {
+            stash: 11,
+            total: 1000,
+            active: 400,
+            unlocking: vec![
+                UnlockChunk { value: 100, era: 1 + 3 },
+                UnlockChunk { value: 200, era: 2 + 3 },
+                UnlockChunk { value: 300, era: 2 + 3 },
+            ],
+            ..Default::default()
+        }));
+
+        // The last chunk is taken whole, the one before is split
+        assert_ok!(Staking::rebond(Origin::signed(10), StakingBalance::Kton(400)));
+        assert_eq!(Staking::ledger(&10), Some(StakingLedger {
+            stash: 11,
+            total: 1000,
+            active: 800,
+            unlocking: vec![
+                UnlockChunk { value: 100, era: 1 + 3 },
+                UnlockChunk { value: 100, era: 2 + 3 },
+            ],
+            ..Default::default()
+        }));
+
+        // The oldest chunk can still be withdrawn once unlocked
+        start_era(4);
+        assert_ok!(Staking::withdraw_unbonded(Origin::signed(10)));
+        assert_eq!(Staking::ledger(&10), Some(StakingLedger {
+            stash: 11,
+            total: 900,
+            active: 800,
+            unlocking: vec![UnlockChunk { value: 100, era: 2 + 3 }],
+            ..Default::default()
+        }));
+    });
+}
+
+#[test]
+fn ring_and_kton_can_be_bonded_together() {
+    // RING is bonded alongside KTON, weighted by `RingKtonRatio`
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        // Account 3 bonds some RING, then some KTON, controlled by account 5
+        assert_ok!(Staking::bond(Origin::signed(3), 5, StakingBalance::Ring(200), RewardDestination::Stash));
+        assert_ok!(Staking::bond_extra(Origin::signed(3), StakingBalance::Kton(100)));
+        assert_eq!(Staking::ledger(&5), Some(StakingLedger {
+            stash: 3,
+            total: 100,
+            active: 100,
+            unlocking: vec![],
+            total_ring: 200,
+            active_ring: 200,
+            ring_unlocking: vec![],
+        }));
+
+        // 10 RING weigh as much as 1 KTON
+        assert_eq!(Staking::slashable_balance_of(&3), 100 + 200 / 10);
+
+        // Unbonding RING leaves the KTON bonded
+        assert_ok!(Staking::unbond(Origin::signed(5), StakingBalance::Ring(50)));
+        assert_eq!(Staking::ledger(&5), Some(StakingLedger {
+            stash: 3,
+            total: 100,
+            active: 100,
+            unlocking: vec![],
+            total_ring: 200,
+            active_ring: 150,
+            ring_unlocking: vec![UnlockChunk { value: 50, era: 3 }],
+        }));
+        assert_eq!(Staking::slashable_balance_of(&3), 100 + 200 / 10);
+    });
+}
+
+#[test]
+fn ring_locked_in_deposits_can_not_be_bonded() {
+    // The same RING can not earn KTON in a deposit and be bonded at once
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        assert_ok!(Kton::deposit(Origin::signed(3), 200, 12));
+
+        // Only the 100 RING left out of the deposit are bonded
+        assert_ok!(Staking::bond(Origin::signed(3), 5, StakingBalance::Ring(300), RewardDestination::Stash));
+        assert_eq!(Staking::ledger(&5).map(|l| l.total_ring), Some(100));
+        assert_ok!(Staking::bond_extra(Origin::signed(3), StakingBalance::Ring(100)));
+        assert_eq!(Staking::ledger(&5).map(|l| l.total_ring), Some(100));
+
+        // Nor can the bonded RING be deposited
+        assert_noop!(
+            Kton::deposit(Origin::signed(3), 50, 12),
+            "account liquidity restrictions prevent withdrawal"
+        );
+    });
+}
+
+#[test]
+fn reaping_one_currency_keeps_the_bond_in_the_other() {
+    // Both currencies report their reaped accounts to staking
+    with_externalities(&mut ExtBuilder::default()
+        .existential_deposit(1)
+        .build(), || {
+        assert_ok!(Staking::bond_extra(Origin::signed(11), StakingBalance::Ring(100)));
+
+        // The KTON of 11 is reaped, its RING stays bonded
+        let _ = Kton::make_free_balance_be(&11, 0);
+        let ledger = Staking::ledger(&10).unwrap();
+        assert_eq!((ledger.total, ledger.active, ledger.total_ring, ledger.active_ring), (0, 0, 100, 100));
+        assert_eq!(Staking::bonded(&11), Some(10));
+        assert!(<Validators<Test>>::exists(&11));
+
+        // The stash is cleared once its RING is reaped too
+        let _ = Ring::make_free_balance_be(&11, 0);
+        assert_eq!(Staking::ledger(&10), None);
+        assert_eq!(Staking::bonded(&11), None);
+        assert!(!<Validators<Test>>::exists(&11));
+    });
+}
+
+#[test]
+fn slash_applies_to_ring_and_kton() {
+    // A slash takes the same part of the bonded RING and KTON
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        assert_ok!(Staking::bond(Origin::signed(3), 5, StakingBalance::Ring(200), RewardDestination::Stash));
+        assert_ok!(Staking::bond_extra(Origin::signed(3), StakingBalance::Kton(100)));
+        assert_eq!(Staking::slashable_balance_of(&3), 120);
+
+        // Slash half of the voting power
+        slashing::apply_slash::<Test>(UnappliedSlash {
+            era: 0,
+            validator: 3,
+            own: 60,
+            others: vec![],
+            reporters: vec![],
+        });
+
+        assert_eq!(Staking::ledger(&5), Some(StakingLedger {
+            stash: 3,
+            total: 50,
+            active: 50,
+            unlocking: vec![],
+            total_ring: 100,
+            active_ring: 100,
+            ring_unlocking: vec![],
+        }));
+        assert_eq!(Ring::free_balance(&3), 300 - 100);
+        assert_eq!(Kton::free_balance(&3), 300 - 50);
+        assert_eq!(Staking::slashable_balance_of(&3), 60);
+    });
+}
+
+#[test]
+fn era_history_is_kept_for_history_depth() {
+    // The elected set, exposures, slot stake and total reward of past eras can be queried
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        assert_eq_uvec!(Staking::eras_elected(0), vec![11, 21]);
+        assert_eq!(Staking::eras_slot_stake(0), Some(1125));
+        assert_eq!(Staking::eras_exposures(0).len(), 2);
+        assert_eq!(Staking::eras_total_reward(0), None);
+
+        start_era(1);
+
+        // Era 0 is over, its snapshot is still there along with its reward
+        assert_eq_uvec!(Staking::eras_elected(0), vec![11, 21]);
+        assert_eq!(Staking::eras_slot_stake(0), Some(1125));
+        assert!(Staking::eras_total_reward(0).is_some());
+        assert_eq_uvec!(Staking::eras_elected(1), Staking::current_elected());
+        assert_eq!(Staking::eras_exposures(1), Staking::current_elected()
+            .into_iter()
+            .map(|v| {
+                let exposure = Staking::stakers(&v);
+                (v, exposure)
+            })
+            .collect::<Vec<_>>());
+        assert_eq!(Staking::eras_total_reward(1), None);
+
+        // Era 0 is out of history once `HistoryDepth` eras have ended after it
+        start_era(HistoryDepth::get());
+        assert_eq_uvec!(Staking::eras_elected(0), vec![11, 21]);
+        start_era(HistoryDepth::get() + 1);
+        assert!(Staking::eras_elected(0).is_empty());
+        assert!(Staking::eras_exposures(0).is_empty());
+        assert_eq!(Staking::eras_slot_stake(0), None);
+        assert_eq!(Staking::eras_total_reward(0), None);
+        assert!(Staking::eras_elected(1).len() > 0);
+    });
+}
+
+#[test]
+fn commission_change_applies_at_next_era_boundary() {
+    // A validator backed in the running era can't change its commission before the next era
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        assert_ok!(Staking::validate(Origin::signed(10), ValidatorPrefs { unstake_threshold: 3, commission: Perbill::from_percent(10), blocked: false }));
+        assert_eq!(Staking::validators(11).commission, Perbill::zero());
+        assert_eq!(Staking::pending_commission(11), Perbill::from_percent(10));
+
+        // Chilling and validating again doesn't apply it either
+        assert_ok!(Staking::chill(Origin::signed(10)));
+        assert!(!<PendingCommission<Test>>::exists(&11));
+        assert_ok!(Staking::validate(Origin::signed(10), ValidatorPrefs { unstake_threshold: 3, commission: Perbill::from_percent(20), blocked: false }));
+        assert_eq!(Staking::validators(11).commission, Perbill::zero());
+        assert_eq!(Staking::pending_commission(11), Perbill::from_percent(20));
+
+        // The era 1 is paid out with the previous commission, the new one applies after it
+        start_era(1);
+        assert_eq!(Staking::eras_validator_prefs(1, 11).commission, Perbill::zero());
+        assert_eq!(Staking::validators(11).commission, Perbill::from_percent(20));
+        assert!(!<PendingCommission<Test>>::exists(&11));
+
+        start_era(2);
+        assert_eq!(Staking::eras_validator_prefs(2, 11).commission, Perbill::from_percent(20));
+
+        // A new validator sets its commission right away. 40 already controls 41 in the mock, so
+        // the new validator is controlled by 50
+        bond_validator(50, 1000);
+        assert_ok!(Staking::chill(Origin::signed(50)));
+        assert_ok!(Staking::validate(Origin::signed(50), ValidatorPrefs { unstake_threshold: 3, commission: Perbill::from_percent(5), blocked: false }));
+        assert_eq!(Staking::validators(51).commission, Perbill::from_percent(5));
+        assert!(!<PendingCommission<Test>>::exists(&51));
+    });
+}
+
+#[test]
+fn blocked_validator_keeps_its_nominators() {
+    // A validator blocking nominations can't be nominated anymore by new nominators
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        assert_ok!(Staking::validate(Origin::signed(10), ValidatorPrefs { blocked: true, ..Default::default() }));
+
+        bond_nominator(1, 500, vec![21]);
+        assert_noop!(
+            Staking::nominate(Origin::signed(1), vec![11, 21]),
+            "validator is blocking new nominations"
+        );
+
+        // 101 already nominates 11, it can keep doing so
+        assert_ok!(Staking::nominate(Origin::signed(100), vec![21, 11]));
+        assert_eq!(Staking::nominators(101), vec![21, 11]);
+    });
+}
+
+#[test]
+fn only_top_nominators_are_exposed() {
+    // Only the `MaxNominatorRewardedPerValidator` nominators with the highest stake are exposed
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        for i in 0..5u64 {
+            bond_nominator(1000 + i * 2, 1000 + i * 100, vec![31]);
+        }
+
+        start_era(1);
+        assert!(Staking::current_elected().contains(&31));
+
+        let exposure = Staking::stakers(31);
+        assert_eq!(exposure.others.len(), MaxNominatorRewardedPerValidator::get() as usize);
+        assert!(exposure.others.iter().all(|i| i.who != 1001));
+        assert_eq!(exposure.total, exposure.own + exposure.others.iter().map(|i| i.value).sum::<u64>());
+        check_exposure(31);
+    });
+}
+
+#[test]
+fn emergency_mode_keeps_previous_validators_until_forced() {
+    // A failed election keeps the previous set without minting, until root forces a new one
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        assert_noop!(Staking::force_set_validators(vec![11, 31]), "not in emergency mode");
+        <MinimumValidatorCount<Test>>::put(5);
+
+        start_era(1);
+        assert!(Staking::emergency_mode());
+        assert_eq_uvec!(Staking::current_elected(), vec![11, 21]);
+        assert_eq_uvec!(Session::validators(), vec![10, 20]);
+        assert_eq_uvec!(Staking::eras_elected(1), vec![11, 21]);
+        assert_eq!(Staking::eras_stakers(1, 11), Staking::stakers(11));
+
+        start_era(2);
+        assert_eq!(Staking::eras_total_reward(1), Some(0));
+
+        assert_noop!(Staking::force_set_validators(vec![11, 1]), "validator is not bonded");
+        assert_noop!(Staking::force_set_validators(vec![11, 11]), "validator given twice");
+        assert_ok!(Staking::force_set_validators(vec![11, 31]));
+
+        // The forced set is enacted with a new era at the next session
+        start_session(Session::current_index() + 1);
+        assert_eq!(Staking::current_era(), 3);
+        assert!(!Staking::emergency_mode());
+        assert_eq_uvec!(Staking::current_elected(), vec![11, 31]);
+        assert_eq!(Staking::stakers(31), Exposure { total: 1, own: 1, others: vec![] });
+        assert_eq!(Staking::eras_total_reward(2), Some(0));
+    });
+}
+
+#[test]
+fn offline_records_decay_and_are_pruned() {
+    // Each offline report counts one less every era, and is dropped after `OfflineRecordsDepth` eras
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        Staking::on_offline_validator(10, 3);
+        assert_eq!(Staking::offline_records(0, 11), 3);
+        assert_eq!(Staking::slash_count(&11), 3);
+
+        // The decay applies whether the validator is still elected or not
+        assert_ok!(Staking::chill(Origin::signed(10)));
+        start_era(1);
+        assert_eq!(Staking::slash_count(&11), 2);
+
+        Staking::on_offline_validator(10, 1);
+        assert_eq!(Staking::slash_count(&11), 3);
+
+        start_era(2);
+        assert_eq!(Staking::slash_count(&11), 1);
+
+        start_era(OfflineRecordsDepth::get() - 1);
+        assert_eq!(Staking::offline_records(0, 11), 3);
+        assert_eq!(Staking::slash_count(&11), 0);
+
+        start_era(OfflineRecordsDepth::get());
+        assert_eq!(Staking::offline_records(0, 11), 0);
+        assert_eq!(Staking::offline_records(1, 11), 1);
+    });
+}
+
+#[test]
+fn offline_slash_is_applied_after_the_defer_duration() {
+    // The slash is recorded when the validator is reported, and only applied `SlashDeferDuration`
+    // eras later
+    with_externalities(&mut ExtBuilder::default()
+        .slash_defer_duration(2)
+        .build(), || {
+        let power = Staking::slashable_balance_of(&11);
+
+        // Over the unstake threshold of 3
+        Staking::on_offline_validator(10, 4);
+        let unapplied = Staking::unapplied_slashes(2);
+        assert_eq!(unapplied.len(), 1);
+        assert_eq!(unapplied[0].validator, 11);
+        assert!(unapplied[0].own > 0);
+        assert_eq!(Staking::slashable_balance_of(&11), power);
+
+        start_era(1);
+        assert_eq!(Staking::slashable_balance_of(&11), power);
+
+        start_era(2);
+        assert!(Staking::unapplied_slashes(2).is_empty());
+        assert_eq!(Staking::slashable_balance_of(&11), power - unapplied[0].own);
+    });
+}
+
+#[test]
+fn cancelled_slash_is_never_applied() {
+    with_externalities(&mut ExtBuilder::default()
+        .slash_defer_duration(2)
+        .build(), || {
+        let power_11 = Staking::slashable_balance_of(&11);
+        let power_21 = Staking::slashable_balance_of(&21);
+
+        Staking::on_offline_validator(10, 4);
+        Staking::on_offline_validator(20, 4);
+        assert_eq!(Staking::unapplied_slashes(2).len(), 2);
+
+        assert!(Staking::cancel_deferred_slash(Origin::signed(10), 2, vec![0]).is_err());
+        assert_noop!(
+            Staking::cancel_deferred_slash(Origin::ROOT, 2, vec![2]),
+            "slash record index out of bounds"
+        );
+
+        // Only the slash of 11 is cancelled
+        assert_ok!(Staking::cancel_deferred_slash(Origin::ROOT, 2, vec![0]));
+        assert_eq!(Staking::unapplied_slashes(2).len(), 1);
+        assert_eq!(Staking::unapplied_slashes(2)[0].validator, 21);
+
+        start_era(2);
+        start_era(3);
+        assert_eq!(Staking::slashable_balance_of(&11), power_11);
+        assert!(Staking::slashable_balance_of(&21) < power_21);
+    });
+}
+
+#[test]
+fn slashes_within_a_span_do_not_stack() {
+    // A span ends with the first slash for an offence in it, the offences committed until then are
+    // only punished once
+    with_externalities(&mut ExtBuilder::default()
+        .slash_defer_duration(1)
+        .build(), || {
+        start_era(2);
+        let owns = |era| Staking::unapplied_slashes(era).iter().map(|s| s.own).collect::<Vec<_>>();
+
+        Staking::slash_validator(1, &11, 100, vec![]);
+        assert_eq!(Staking::slashing_spans(&11), slashing::SlashingSpans {
+            span_index: 1,
+            last_start: 3,
+            prior: vec![0],
+        });
+        // Eras 0 and 1 are in the first span of 11, only the part over 100 is slashed
+        Staking::slash_validator(0, &11, 60, vec![]);
+        Staking::slash_validator(0, &11, 150, vec![]);
+        assert_eq!(owns(3), vec![100, 50]);
+        assert_eq!(Staking::span_slash(11, 0), 150);
+
+        // A cancelled slash is taken back from its span, the span stays ended
+        assert_ok!(Staking::cancel_deferred_slash(Origin::ROOT, 3, vec![0, 1]));
+        assert_eq!(Staking::span_slash(11, 0), 0);
+        Staking::slash_validator(0, &11, 120, vec![]);
+        assert_eq!(owns(3), vec![120]);
+        assert_eq!(Staking::slashing_spans(&11).span_index, 1);
+
+        // The offences of the next span are slashed on their own
+        start_era(3);
+        Staking::slash_validator(3, &11, 50, vec![]);
+        assert_eq!(owns(4), vec![50]);
+        assert_eq!(Staking::span_slash(11, 0), 120);
+        assert_eq!(Staking::span_slash(11, 1), 50);
+    });
+}
+
+#[test]
+fn equivocations_are_slashed_and_reporters_paid() {
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        let power_11 = Staking::slashable_balance_of(&11);
+        let power_101 = Staking::slashable_balance_of(&101);
+        let kton_1 = Kton::free_balance(&1);
+        let report = |reporter, first, second| {
+            Staking::report_equivocation(Origin::signed(reporter), EquivocationProof::Aura(first, second))
+        };
+
+        // The blocks of slot 0 are sealed by its author, the authority of 10
+        assert_noop!(report(1, aura_block(0, 1, 20), aura_block(0, 2, 20)), "bad signature");
+        assert_noop!(report(1, aura_block(0, 1, 10), aura_block(0, 1, 10)), "blocks are the same");
+        assert_noop!(report(1, aura_block(0, 1, 10), aura_block(2, 2, 10)), "blocks are not in the same slot");
+        let mut unsealed = aura_block(0, 2, 10);
+        unsealed.digest.pop();
+        assert_noop!(report(1, aura_block(0, 1, 10), unsealed), "block is not sealed");
+
+        assert_ok!(report(1, aura_block(0, 1, 10), aura_block(0, 2, 10)));
+        assert_eq!(Staking::offence_reports(0, (OffenceKind::AuraEquivocation, 0)), vec![11]);
+        assert!(Staking::reported_equivocations((OffenceKind::AuraEquivocation, 11, 0, 0)));
+
+        // One offender out of two validators, its whole exposure is slashed
+        assert_eq!(Staking::slashable_balance_of(&11), 0);
+        let slashed = power_11 + power_101 - Staking::slashable_balance_of(&101);
+        assert_eq!(Kton::free_balance(&1), kton_1 + SlashRewardFraction::get() * slashed);
+
+        // An equivocation is reported once, and an offender once per kind and session
+        assert_noop!(report(2, aura_block(0, 1, 10), aura_block(0, 2, 10)), "equivocation already reported");
+        assert_noop!(report(2, aura_block(2, 3, 10), aura_block(2, 4, 10)), "offence already reported");
+        start_session(1);
+        assert_noop!(report(2, aura_block(0, 1, 10), aura_block(0, 2, 10)), "equivocation already reported");
+    });
+}
+
+#[test]
+fn grandpa_equivocations_are_checked_against_the_votes() {
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        let (a, b) = (H256::repeat_byte(1), H256::repeat_byte(2));
+        // Votes of the authority of 20, in round 1 of the set 0 unless said otherwise
+        let vote = |message, round, set_id| grandpa_vote(message, round, set_id, 20);
+        let report = |authority_index, first, second| {
+            Staking::report_equivocation(Origin::signed(1), EquivocationProof::Grandpa {
+                round: 1,
+                set_id: 0,
+                authority_index,
+                first,
+                second,
+            })
+        };
+
+        assert_noop!(
+            report(1, vote(GrandpaVote::Prevote(a, 1), 1, 0), vote(GrandpaVote::Precommit(b, 1), 1, 0)),
+            "votes are not of the same kind"
+        );
+        assert_noop!(
+            report(1, vote(GrandpaVote::Prevote(a, 1), 1, 0), vote(GrandpaVote::Prevote(a, 1), 1, 0)),
+            "votes are the same"
+        );
+        // The votes are signed with their round and set
+        assert_noop!(
+            report(1, vote(GrandpaVote::Prevote(a, 1), 1, 0), vote(GrandpaVote::Prevote(b, 1), 2, 0)),
+            "bad signature"
+        );
+        assert_noop!(
+            report(1, vote(GrandpaVote::Prevote(a, 1), 1, 0), vote(GrandpaVote::Prevote(b, 1), 1, 1)),
+            "bad signature"
+        );
+        assert_noop!(
+            report(0, vote(GrandpaVote::Prevote(a, 1), 1, 0), vote(GrandpaVote::Prevote(b, 1), 1, 0)),
+            "bad signature"
+        );
+        assert_noop!(
+            report(2, vote(GrandpaVote::Prevote(a, 1), 1, 0), vote(GrandpaVote::Prevote(b, 1), 1, 0)),
+            "unknown authority"
+        );
+        // A heartbeat signature is not a vote, even when made with the same key
+        let heartbeat = Heartbeat { block_number: 1, session_index: 0, authority_index: 1 };
+        let payload = heartbeat::signed_payload(heartbeat::HEARTBEAT_TAG, &heartbeat);
+        assert_noop!(
+            report(1, (GrandpaVote::Prevote(a, 1), TestSignature(20, payload)), vote(GrandpaVote::Prevote(b, 1), 1, 0)),
+            "bad signature"
+        );
+
+        assert_ok!(report(
+            1,
+            vote(GrandpaVote::Precommit(a, 1), 1, 0),
+            vote(GrandpaVote::Precommit(b, 2), 1, 0)
+        ));
+        assert_eq!(Staking::offence_reports(0, (OffenceKind::GrandpaEquivocation, 0)), vec![21]);
+        assert!(Staking::reported_equivocations((OffenceKind::GrandpaEquivocation, 21, 1, 0)));
+    });
+}
+
+#[test]
+fn offences_are_slashed_in_the_era_of_their_session() {
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        start_era(1);
+        let power_21 = Staking::slashable_balance_of(&21);
+        let power_101 = Staking::slashable_balance_of(&101);
+        let (kton_1, kton_2) = (Kton::free_balance(&1), Kton::free_balance(&2));
+
+        // Session 1 is in era 0, the exposure of 21 in era 0 is slashed
+        assert_ok!(<Staking as ReportOffence<_>>::report_offence(vec![1, 2], Offence {
+            kind: OffenceKind::GrandpaEquivocation,
+            session_index: 1,
+            validator_set_count: 2,
+            offenders: vec![21],
+        }));
+        assert_eq!(Staking::span_slash(21, 0), Staking::eras_stakers(0, 21).own);
+
+        // The reporters share the reward
+        let slashed = power_21 + power_101 - Staking::slashable_balance_of(&21) - Staking::slashable_balance_of(&101);
+        let reward = SlashRewardFraction::get() * slashed / 2;
+        assert!(reward > 0);
+        assert_eq!(Kton::free_balance(&1), kton_1 + reward);
+        assert_eq!(Kton::free_balance(&2), kton_2 + reward);
+
+        // Unresponsive validators are counted offline in the era of their session
+        let offence = |offenders| Offence {
+            kind: OffenceKind::Unresponsiveness,
+            session_index: 1,
+            validator_set_count: 2,
+            offenders,
+        };
+        assert_ok!(<Staking as ReportOffence<_>>::report_offence(vec![], offence(vec![11])));
+        assert_eq!(Staking::offline_records(0, 11), 1);
+        assert_eq!(Staking::offline_records(1, 11), 0);
+        assert_eq!(Staking::span_slash(11, 0), 0);
+
+        // Sessions out of history can not be reported
+        start_era(HistoryDepth::get() + 1);
+        assert_noop!(
+            <Staking as ReportOffence<_>>::report_offence(vec![], offence(vec![11])),
+            "offence is out of history"
+        );
+    });
+}
+
+#[test]
+fn heartbeats_are_checked_against_the_session_authorities() {
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        let heartbeat = |session_index, authority_index| Heartbeat { block_number: 1, session_index, authority_index };
+        let signed = |signer, heartbeat: &Heartbeat<BlockNumber>| TestSignature(signer, heartbeat::signed_payload(heartbeat::HEARTBEAT_TAG, heartbeat));
+
+        // The authority of 10 is the first one
+        let alive = heartbeat(0, 0);
+        assert!(Staking::heartbeat(Origin::signed(10), alive.clone(), signed(10, &alive)).is_err());
+        assert_noop!(Staking::heartbeat(Origin::NONE, alive.clone(), signed(20, &alive)), "bad signature");
+        assert_noop!(
+            Staking::heartbeat(Origin::NONE, heartbeat(0, 2), signed(10, &heartbeat(0, 2))),
+            "unknown authority"
+        );
+        assert_noop!(
+            Staking::heartbeat(Origin::NONE, heartbeat(1, 0), signed(10, &heartbeat(1, 0))),
+            "heartbeat is not for the current session"
+        );
+        assert_eq!(
+            Staking::validate_unsigned(&Call::heartbeat(alive.clone(), signed(20, &alive))),
+            TransactionValidity::Invalid(0)
+        );
+
+        // The heartbeat is valid until the session ends, at block 2
+        System::set_block_number(1);
+        assert_eq!(
+            Staking::validate_unsigned(&Call::heartbeat(alive.clone(), signed(10, &alive))),
+            TransactionValidity::Valid {
+                priority: 0,
+                requires: vec![],
+                provides: vec![(0 as SessionIndex, 0u32).encode()],
+                longevity: 1,
+                propagate: true,
+            }
+        );
+        assert_ok!(Staking::heartbeat(Origin::NONE, alive.clone(), signed(10, &alive)));
+        assert!(Staking::received_heartbeats(0, 0));
+
+        // One heartbeat per authority and session
+        assert_eq!(
+            Staking::validate_unsigned(&Call::heartbeat(alive.clone(), signed(10, &alive))),
+            TransactionValidity::Invalid(0)
+        );
+        assert_noop!(
+            Staking::heartbeat(Origin::NONE, alive.clone(), signed(10, &alive)),
+            "heartbeat already received"
+        );
+    });
+}
+
+#[test]
+fn validators_without_heartbeat_are_counted_offline() {
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        start_session(1);
+        let signed = |signer, heartbeat: &Heartbeat<BlockNumber>| {
+            TestSignature(signer, heartbeat::signed_payload(heartbeat::HEARTBEAT_TAG, heartbeat))
+        };
+        // 21 is slashed at its first offline report
+        assert_ok!(Staking::validate(Origin::signed(20), ValidatorPrefs {
+            unstake_threshold: 0,
+            ..Default::default()
+        }));
+
+        // Only 21 is reported in session 1
+        let alive = Heartbeat { block_number: 1, session_index: 1, authority_index: 0 };
+        assert_ok!(Staking::heartbeat(Origin::NONE, alive.clone(), signed(10, &alive)));
+        Staking::check_liveness(1);
+        assert_eq!(Staking::offence_reports(0, (OffenceKind::Unresponsiveness, 1)), vec![21]);
+        assert_eq!(Staking::offline_records(0, 11), 0);
+        assert_eq!(Staking::offline_records(0, 21), 1);
+        assert!(Staking::span_slash(21, 0) > 0);
+        assert_eq!(
+            Staking::span_slash(21, 0),
+            Staking::offline_slash() * Staking::eras_stakers(0, 21).total
+        );
+        // The heartbeats of the session are forgotten
+        assert!(!Staking::received_heartbeats(1, 0));
+
+        // 11 is within its unstake threshold, it is only warned
+        Staking::check_liveness(2);
+        assert_eq!(Staking::offence_reports(0, (OffenceKind::Unresponsiveness, 2)), vec![11, 21]);
+        assert_eq!(Staking::offline_records(0, 11), 1);
+        assert_eq!(Staking::slash_count(&11), 1);
+        assert_eq!(Staking::span_slash(11, 0), 0);
+    });
+}
+
+#[test]
+fn liveness_is_not_checked_in_the_first_session() {
+    // Heartbeats are only sent from the session the module is enacted in
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        assert_eq!(Staking::liveness_checked_from(), None);
+
+        Staking::check_liveness(0);
+        assert_eq!(Staking::liveness_checked_from(), Some(1));
+        assert!(Staking::offence_reports(0, (OffenceKind::Unresponsiveness, 0)).is_empty());
+        assert_eq!(Staking::offline_records(0, 11), 0);
+        assert_eq!(Staking::offline_records(0, 21), 0);
+
+        Staking::check_liveness(1);
+        assert_eq!(Staking::offence_reports(0, (OffenceKind::Unresponsiveness, 1)), vec![11, 21]);
+    });
+}
+
+#[test]
+fn alive_validators_are_not_reported() {
+    // `start_session` sends the heartbeats of all the validators
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        start_era(1);
+        for session_index in 0..3 {
+            assert!(Staking::offence_reports(0, (OffenceKind::Unresponsiveness, session_index)).is_empty());
+        }
+        assert_eq!(Staking::slash_count(&11), 0);
+        assert_eq!(Staking::slash_count(&21), 0);
+        assert_eq!(Staking::span_slash(11, 0), 0);
+        assert_eq!(Staking::span_slash(21, 0), 0);
+    });
+}
+
+#[test]
+fn election_window_is_open_during_last_session_of_era() {
+    // The snapshot is taken for the offchain election, then dropped at the new era
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        assert!(Staking::snapshot_validators().is_none());
+
+        start_session(1);
+        assert!(Staking::snapshot_validators().is_none());
+
+        // Session 2 is the last session of era 0
+        start_session(2);
+        assert_eq!(Staking::snapshot_validators().map(|v| v.len()), Some(3));
+        assert_eq!(Staking::snapshot_nominators(), Some(vec![(101, 500, vec![11, 21])]));
+
+        // The offchain election elects the same validators as the on-chain one
+        let solution = election::compute_solution::<Test>().unwrap();
+        assert_eq_uvec!(solution.iter().map(|(v, _)| *v).collect::<Vec<_>>(), vec![11, 21]);
+        for (_, exposure) in &solution {
+            assert_eq!(exposure.own, 1000);
+            assert_eq!(exposure.total, exposure.own + exposure.others.iter().map(|i| i.value).sum::<u64>());
+        }
+
+        // No solution was submitted, the election was run on-chain
+        start_era(1);
+        assert!(Staking::snapshot_validators().is_none());
+        assert!(Staking::queued_elected().is_none());
+        assert_eq_uvec!(Staking::current_elected(), vec![11, 21]);
+    });
+}
+
+#[test]
+fn election_score_comparison() {
+    // A higher minimal support wins, then a higher total, then a lower variance
+    assert!(election::is_score_better((10, 20, 200), (9, 30, 100)));
+    assert!(election::is_score_better((10, 21, 300), (10, 20, 200)));
+    assert!(election::is_score_better((10, 20, 199), (10, 20, 200)));
+    assert!(!election::is_score_better((10, 20, 200), (10, 20, 200)));
+    assert!(!election::is_score_better((9, 30, 100), (10, 20, 200)));
+}
+
+#[test]
+fn election_solutions_are_checked_against_the_snapshot() {
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        start_session(2);
+
+        let exposure = |own, others: Vec<(AccountId, Balance)>| Exposure {
+            total: own + others.iter().map(|(_, value)| value).sum::<Balance>(),
+            own,
+            others: others.into_iter().map(|(who, value)| IndividualExposure { who, value }).collect(),
+        };
+        // Signed by the authority of 10 unless `signer` says otherwise
+        let submit = |era: EraIndex, elected: Vec<(AccountId, Exposure<AccountId, Balance>)>, signer: AccountId| {
+            let signature = TestSignature(signer, heartbeat::signed_payload(heartbeat::SOLUTION_TAG, &(era, &elected)));
+            let call = Call::submit_election_solution(era, elected.clone(), 0, signature.clone());
+            let validity = Staking::validate_unsigned(&call);
+            let result = Staking::submit_election_solution(Origin::NONE, era, elected, 0, signature);
+            // The transaction is valid if and only if the call succeeds
+            assert_eq!(result.is_ok(), validity != TransactionValidity::Invalid(0));
+            result
+        };
+        let balanced = vec![(11, exposure(1000, vec![(101, 250)])), (21, exposure(1000, vec![(101, 250)]))];
+        let unbalanced = vec![(11, exposure(1000, vec![(101, 500)])), (21, exposure(1000, vec![(101, 0)]))];
+
+        assert_noop!(submit(1, balanced.clone(), 10), "solution is not for the current era");
+        assert_noop!(submit(0, balanced.clone(), 20), "bad signature");
+        assert_noop!(
+            submit(0, vec![(11, exposure(1000, vec![(101, 500)]))], 10),
+            "wrong number of elected validators"
+        );
+        assert_noop!(
+            submit(0, vec![(41, exposure(1000, vec![])), (11, exposure(1000, vec![(101, 500)]))], 10),
+            "elected validator is not a candidate"
+        );
+        assert_noop!(
+            submit(0, vec![(31, exposure(1, vec![(101, 500)])), (11, exposure(1000, vec![(101, 500)]))], 10),
+            "validator not nominated"
+        );
+        assert_noop!(
+            submit(0, vec![(11, exposure(1000, vec![(101, 300)])), (21, exposure(1000, vec![(101, 300)]))], 10),
+            "nominator stake exceeded"
+        );
+        let mut wrong_total = balanced.clone();
+        wrong_total[0].1.total += 1;
+        assert_noop!(submit(0, wrong_total, 10), "wrong total stake");
+
+        assert_ok!(submit(0, unbalanced.clone(), 10));
+        assert_eq!(Staking::queued_score(), Some((1000, 2500, 1500 * 1500 + 1000 * 1000)));
+
+        // A better solution replaces the queued one, with a higher priority in the pool. It is
+        // valid until the window closes with the session
+        assert_eq!(Staking::session_start(), 2);
+        System::set_block_number(3);
+        let signature = TestSignature(10, heartbeat::signed_payload(heartbeat::SOLUTION_TAG, &(0 as EraIndex, &balanced)));
+        let call = Call::submit_election_solution(0, balanced.clone(), 0, signature);
+        match Staking::validate_unsigned(&call) {
+            TransactionValidity::Valid { priority, longevity, .. } => {
+                assert_eq!(priority, 1250);
+                assert_eq!(longevity, 1);
+            },
+            _ => panic!("the better solution is valid"),
+        }
+        assert_ok!(submit(0, balanced.clone(), 10));
+        assert_eq!(Staking::queued_elected(), Some(balanced.clone()));
+        assert_eq!(Staking::queued_score(), Some((1250, 2500, 2 * 1250 * 1250)));
+
+        assert_noop!(submit(0, unbalanced, 10), "solution is not better than the queued one");
+        assert_noop!(submit(0, balanced.clone(), 10), "solution is not better than the queued one");
+
+        // The queued solution elects the validators of the next era
+        start_era(1);
+        assert_eq!(Staking::stakers(11), exposure(1000, vec![(101, 250)]));
+    });
+}
+
+#[test]
+fn election_solutions_expose_the_nominators_with_the_highest_stake() {
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        // 101 and 5 more nominators back 11
+        for i in 0..5u64 {
+            bond_nominator(1000 + i * 2, 1000 + i * 100, vec![11]);
+        }
+        start_session(2);
+
+        let submit = |elected: Vec<(AccountId, Exposure<AccountId, Balance>)>| {
+            let signature = TestSignature(10, heartbeat::signed_payload(heartbeat::SOLUTION_TAG, &(0 as EraIndex, &elected)));
+            Staking::submit_election_solution(Origin::NONE, 0, elected, 0, signature)
+        };
+        let solution = election::compute_solution::<Test>().unwrap();
+        let exposure_11 = &solution.iter().find(|(v, _)| *v == 11).unwrap().1;
+        assert_eq_uvec!(exposure_11.others.iter().map(|i| i.who).collect::<Vec<_>>(), vec![1003, 1005, 1007, 1009]);
+
+        // 101 has the lowest stake, it can not be exposed in place of another nominator
+        let mut tampered = solution.clone();
+        for (validator, exposure) in tampered.iter_mut() {
+            if *validator == 11 {
+                exposure.others.retain(|i| i.who != 1009);
+                exposure.others.push(IndividualExposure { who: 101, value: 0 });
+                exposure.total = exposure.own + exposure.others.iter().map(|i| i.value).sum::<Balance>();
+            }
+        }
+        assert_noop!(submit(tampered), "exposed nominators are not the ones with the highest stake");
+
+        // Nor can a nominator be left out if there is room for it
+        let mut tampered = solution.clone();
+        for (validator, exposure) in tampered.iter_mut() {
+            if *validator == 11 {
+                exposure.others.retain(|i| i.who != 1003);
+                exposure.total = exposure.own + exposure.others.iter().map(|i| i.value).sum::<Balance>();
+            }
+        }
+        assert_noop!(submit(tampered), "exposed nominators are not the ones with the highest stake");
+
+        assert_ok!(submit(solution));
+    });
+}
+
+#[test]
+fn staking_changes_are_rejected_while_the_election_window_is_open() {
+    with_externalities(&mut ExtBuilder::default()
+        .build(), || {
+        start_session(2);
+
+        assert_noop!(
+            Staking::bond(Origin::signed(3), 5, StakingBalance::Kton(100), RewardDestination::Stash),
+            "election window is open"
+        );
+        assert_noop!(Staking::bond_extra(Origin::signed(11), StakingBalance::Kton(100)), "election window is open");
+        assert_noop!(Staking::unbond(Origin::signed(10), StakingBalance::Kton(100)), "election window is open");
+        assert_noop!(Staking::rebond(Origin::signed(10), StakingBalance::Kton(100)), "election window is open");
+        assert_noop!(Staking::withdraw_unbonded(Origin::signed(10)), "election window is open");
+        assert_noop!(Staking::validate(Origin::signed(100), ValidatorPrefs::default()), "election window is open");
+        assert_noop!(Staking::nominate(Origin::signed(10), vec![21]), "election window is open");
+        assert_noop!(Staking::chill(Origin::signed(100)), "election window is open");
+
+        // The window is closed with the new era
+        start_era(1);
+        assert_ok!(Staking::unbond(Origin::signed(10), StakingBalance::Kton(100)));
+        assert_ok!(Staking::chill(Origin::signed(100)));
+    });
+}
+
+#[test]
+fn slot_authors_earn_era_reward_points() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        // The authorities take their slots in turn, in the order of the session validators
+        assert_eq!(Session::validators(), vec![10, 20]);
+        for slot in 4..9 {
+            Staking::reward_slot_author(slot);
+        }
+
+        // The points go to the stashes of the controllers
+        let points = Staking::eras_reward_points(0);
+        assert_eq!(points.total, 5);
+        assert_eq!(points.points_of(&11), 3);
+        assert_eq!(points.points_of(&21), 2);
+        assert_eq!(points.points_of(&10), 0);
+
+        // Points are credited to the running era only
+        start_era(1);
+        let author = Session::validators()[1];
+        Staking::reward_slot_author(3);
+        let points = Staking::eras_reward_points(1);
+        assert_eq!(points.total, 1);
+        assert_eq!(points.points_of(&Staking::ledger(&author).unwrap().stash), 1);
+        assert_eq!(Staking::eras_reward_points(0).total, 5);
+    });
+}
+
+#[test]
+fn era_reward_is_split_between_stakers_kton_holders_and_treasury() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        let treasury = TreasuryAccount::get();
+        let kton_pot = Kton::sys_acc();
+        let reward = Staking::session_reward() * Staking::current_era_total_reward();
+        assert!(reward > 0);
+
+        Staking::reward_by_ids(vec![(11, 1)]);
+        start_era(1);
+        let stakers_reward = StakersRatio::get() * reward;
+        let kton_holders_reward = KtonHoldersRatio::get() * reward;
+        assert_eq!(Staking::eras_total_reward(0), Some(reward));
+        assert_eq!(Staking::eras_validator_reward(0), Some(stakers_reward));
+        assert_eq!(Ring::free_balance(&kton_pot), kton_holders_reward);
+        // The treasury gets what is left
+        assert_eq!(Ring::free_balance(&treasury), reward - stakers_reward - kton_holders_reward);
+    });
+}
+
+#[test]
+fn reward_of_an_era_without_points_goes_to_the_treasury() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        let treasury = TreasuryAccount::get();
+        let reward = Staking::session_reward() * Staking::current_era_total_reward();
+
+        // Nobody authored a block, so nobody could claim the stakers' part
+        start_era(1);
+        assert_eq!(Staking::eras_validator_reward(0), None);
+        assert_eq!(Ring::free_balance(&Kton::sys_acc()), KtonHoldersRatio::get() * reward);
+        assert_eq!(Ring::free_balance(&treasury), reward - KtonHoldersRatio::get() * reward);
+        assert_noop!(Staking::payout_stakers(Origin::signed(1), 11, 0), "no reward for this era");
+    });
+}
+
+#[test]
+fn payout_stakers_splits_reward_pro_rata() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        // 11 earned three quarters of the points of the era
+        Staking::reward_by_ids(vec![(11, 3), (21, 1)]);
+        assert_noop!(Staking::payout_stakers(Origin::signed(1), 11, 0), "era not finished yet");
+        start_era(1);
+
+        let era_payout = Staking::eras_validator_reward(0).unwrap();
+        let validator_payout = Perbill::from_percent(75) * era_payout;
+        let exposure = Staking::eras_stakers(0, 11);
+        assert_eq!(exposure.others.iter().map(|i| i.who).collect::<Vec<_>>(), vec![101]);
+        let (ring_11, ring_101) = (Ring::free_balance(&11), Ring::free_balance(&101));
+
+        // The payout of the validator is shared with its nominators in proportion to their stake
+        assert_ok!(Staking::payout_stakers(Origin::signed(1), 11, 0));
+        assert_eq!(
+            Ring::free_balance(&11),
+            ring_11 + Perbill::from_rational_approximation(exposure.own, exposure.total) * validator_payout
+        );
+        assert_eq!(
+            Ring::free_balance(&101),
+            ring_101 + Perbill::from_rational_approximation(exposure.others[0].value, exposure.total) * validator_payout
+        );
+
+        // Each validator is paid once per era
+        assert!(Staking::eras_reward_claimed(0, 11));
+        assert!(!Staking::eras_reward_claimed(0, 21));
+        assert_noop!(Staking::payout_stakers(Origin::signed(1), 11, 0), "reward already claimed");
+        assert_ok!(Staking::payout_stakers(Origin::signed(2), 21, 0));
+        assert_noop!(Staking::payout_stakers(Origin::signed(2), 21, 0), "reward already claimed");
+        assert_noop!(
+            Staking::payout_stakers(Origin::signed(1), 31, 0),
+            "validator has no reward points for this era"
+        );
+    });
+}
+
+#[test]
+fn payout_stakers_is_limited_to_history_depth() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        Staking::reward_by_ids(vec![(11, 1)]);
+        start_era(1);
+        Staking::reward_by_ids(vec![(11, 1)]);
+        start_era(2);
+        // Nobody earned a point in era 2, so there is nothing to claim
+        start_era(3);
+        assert_eq!(Staking::eras_validator_reward(2), None);
+        assert_noop!(Staking::payout_stakers(Origin::signed(1), 11, 2), "no reward for this era");
+
+        start_era(HistoryDepth::get() + 1);
+        assert_noop!(Staking::payout_stakers(Origin::signed(1), 11, 0), "era is out of history");
+        assert_eq!(Staking::eras_validator_reward(0), None);
+        assert_ok!(Staking::payout_stakers(Origin::signed(1), 11, 1));
+    });
+}
+
+#[test]
+fn staked_rewards_are_deposited_and_bonded() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        // A payee the migration could not reach is paid like `Staked`
+        <Payee<Test>>::insert(&11, RewardDestination::StakedDeprecated);
+        Staking::reward_by_ids(vec![(11, 1)]);
+        start_era(1);
+
+        let exposure = Staking::eras_stakers(0, 11);
+        let payout = Perbill::from_rational_approximation(exposure.own, exposure.total) *
+            Staking::eras_validator_reward(0).unwrap();
+        let minted = <kton::MonthlyCompoundCurve as kton::IssuanceCurve<u64, u64>>::compute(
+            RewardDepositMonths::get(),
+            payout
+        ).unwrap();
+        let ledger = Staking::ledger(&10).unwrap();
+        let (ring_before, kton_before) = (Ring::free_balance(&11), Kton::free_balance(&11));
+
+        assert_ok!(Staking::payout_stakers(Origin::signed(1), 11, 0));
+        assert_eq!(Staking::payee(&11), RewardDestination::Staked);
+        // The RING reward is locked in a deposit, and the KTON minted is bonded
+        assert_eq!(Ring::free_balance(&11), ring_before + payout);
+        assert_eq!(Kton::deposit_ledger(&11).unwrap().total, payout);
+        assert_eq!(Kton::free_balance(&11), kton_before + minted);
+        let new_ledger = Staking::ledger(&10).unwrap();
+        assert_eq!(new_ledger.total, ledger.total + minted);
+        assert_eq!(new_ledger.active, ledger.active + minted);
+    });
+}
+
+#[test]
+fn staked_rewards_are_left_free_if_they_can_not_be_deposited() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        <Payee<Test>>::insert(&11, RewardDestination::Staked);
+        Staking::reward_by_ids(vec![(11, 1)]);
+        start_era(1);
+
+        // The RING of 11 is locked for good, none of it can be deposited
+        Ring::set_lock(*b"testlock", &11, u64::max_value(), u64::max_value(), WithdrawReasons::all());
+        let ledger = Staking::ledger(&10).unwrap();
+        let ring_before = Ring::free_balance(&11);
+
+        assert_ok!(Staking::payout_stakers(Origin::signed(1), 11, 0));
+        assert!(Ring::free_balance(&11) > ring_before);
+        assert!(Kton::deposit_ledger(&11).is_none());
+        assert_eq!(Staking::ledger(&10), Some(ledger));
+    });
+}
+
+#[test]
+fn staked_deprecated_payees_are_migrated_from_version_0() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        write_validator_prefs_before_v3();
+        for stash in &[11, 101, 41] {
+            <Payee<Test>>::insert(stash, RewardDestination::StakedDeprecated);
+        }
+        <Payee<Test>>::insert(&21, RewardDestination::Stash);
+        <StorageVersion<Test>>::put(0);
+
+        // The payees of the validator and of the nominator are rewritten, the others are kept
+        migration::on_runtime_upgrade::<Test>();
+        assert_eq!(<StorageVersion<Test>>::get(), migration::CURRENT_VERSION);
+        assert_eq!(Staking::payee(&11), RewardDestination::Staked);
+        assert_eq!(Staking::payee(&101), RewardDestination::Staked);
+        assert_eq!(Staking::payee(&21), RewardDestination::Stash);
+
+        // 41 is idle, its payee is rewritten on its next payout
+        assert_eq!(Staking::payee(&41), RewardDestination::StakedDeprecated);
+    });
+}
+
+#[test]
+fn ledgers_are_migrated_from_version_1() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        use migration::deprecated::v1;
+
+        write_validator_prefs_before_v3();
+        // The ledgers are written in their layout before RING could be bonded
+        let ledgers = [10, 100, 40].iter().map(|c| (*c, Staking::ledger(c).unwrap())).collect::<Vec<_>>();
+        for (controller, ledger) in ledgers.iter().cloned() {
+            <v1::Ledger<Test>>::insert(&controller, v1::StakingLedger {
+                stash: ledger.stash,
+                total: ledger.total,
+                active: ledger.active,
+                unlocking: ledger.unlocking,
+            });
+            assert_eq!(<Ledger<Test>>::get(&controller), None);
+            assert_eq!(Staking::ledger(&controller), Some(ledger));
+        }
+        <StorageVersion<Test>>::put(1);
+
+        // The ledgers of the validator and of the nominator are rewritten, with no RING bonded
+        migration::on_runtime_upgrade::<Test>();
+        assert_eq!(<StorageVersion<Test>>::get(), migration::CURRENT_VERSION);
+        for (controller, ledger) in &ledgers[..2] {
+            assert_eq!(<Ledger<Test>>::get(controller).as_ref(), Some(ledger));
+            assert_eq!(ledger.total_ring, 0);
+        }
+
+        // 41 is idle, its ledger is rewritten when it next changes
+        assert_eq!(<Ledger<Test>>::get(&40), None);
+        assert_ok!(Staking::unbond(Origin::signed(40), StakingBalance::Kton(10)));
+        assert_eq!(<Ledger<Test>>::get(&40).map(|l| l.active), Some(ledgers[2].1.active - 10));
+    });
+}
+
+#[test]
+fn validator_payments_are_migrated_to_commissions_from_version_2() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        use migration::deprecated::v2;
+
+        // The era reward is estimated at 60% of 40% of 160_000_000_000, shared by 2 validators
+        let average_reward = 19_200_000_000;
+        let old_prefs = |validator_payment| v2::ValidatorPrefs { unstake_threshold: 5, validator_payment };
+        let new_prefs = |commission| ValidatorPrefs { unstake_threshold: 5, commission, blocked: false };
+        let payments = vec![(31, 0), (21, 2 * average_reward), (11, average_reward / 10)];
+        for (stash, _) in &payments {
+            <Validators<Test>>::remove(stash);
+        }
+        for (stash, payment) in payments.iter().rev() {
+            <v2::Validators<Test>>::insert(stash, old_prefs(*payment));
+        }
+        <v2::ErasValidatorPrefs<Test>>::insert(&0, &11, old_prefs(average_reward / 10));
+        <v2::ErasValidatorPrefs<Test>>::insert(&0, &21, old_prefs(2 * average_reward));
+        <StorageVersion<Test>>::put(2);
+
+        // The old layout is compact encoded: 1 byte for the threshold, 5 instead of 8 for the payment
+        assert_eq!(old_prefs(average_reward / 10).encode().len(), 1 + 5);
+
+        migration::on_runtime_upgrade::<Test>();
+        assert_eq!(<StorageVersion<Test>>::get(), migration::CURRENT_VERSION);
+        assert_eq!(
+            <Validators<Test>>::enumerate().map(|(stash, _)| stash).collect::<Vec<_>>(),
+            vec![31, 21, 11]
+        );
+        // The commission is the share the payment was of the average reward, up to all of it
+        assert_eq!(Staking::validators(31), new_prefs(Perbill::zero()));
+        assert_eq!(Staking::validators(21), new_prefs(Perbill::one()));
+        assert_eq!(Staking::validators(11), new_prefs(Perbill::from_percent(10)));
+        assert_eq!(Staking::eras_validator_prefs(0, 21), new_prefs(Perbill::one()));
+        assert_eq!(Staking::eras_validator_prefs(0, 11), new_prefs(Perbill::from_percent(10)));
+    });
+}
+
+#[test]
+fn validator_payments_are_migrated_to_no_commission_without_era_reward() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        use migration::deprecated::v2;
+
+        <Validators<Test>>::remove(&11);
+        <v2::Validators<Test>>::insert(&11, v2::ValidatorPrefs { unstake_threshold: 5, validator_payment: 1_000 });
+        <CurrentEraTotalReward<Test>>::put(0);
+        <StorageVersion<Test>>::put(2);
+
+        // Without any reward to share, the payment took none of it
+        migration::on_runtime_upgrade::<Test>();
+        assert_eq!(
+            Staking::validators(11),
+            ValidatorPrefs { unstake_threshold: 5, commission: Perbill::zero(), blocked: false }
+        );
+    });
+}
+
+#[test]
+fn validator_prefs_are_migrated_from_version_3() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        use migration::deprecated::v3;
+
+        let old_prefs = || v3::ValidatorPrefs { unstake_threshold: 5, commission: Perbill::from_percent(10) };
+        let new_prefs = ValidatorPrefs { unstake_threshold: 5, commission: Perbill::from_percent(10), blocked: false };
+        let validators = <Validators<Test>>::enumerate().map(|(stash, _)| stash).collect::<Vec<_>>();
+        for stash in &validators {
+            <Validators<Test>>::remove(stash);
+        }
+        for stash in validators.iter().rev() {
+            <v3::Validators<Test>>::insert(stash, old_prefs());
+        }
+        <v3::ErasValidatorPrefs<Test>>::insert(&0, &11, old_prefs());
+        <StorageVersion<Test>>::put(3);
+
+        migration::on_runtime_upgrade::<Test>();
+        assert_eq!(<StorageVersion<Test>>::get(), migration::CURRENT_VERSION);
+        // The order of the validators is kept
+        assert_eq!(<Validators<Test>>::enumerate().map(|(stash, _)| stash).collect::<Vec<_>>(), validators);
+        for stash in &validators {
+            assert_eq!(Staking::validators(stash), new_prefs);
+        }
+        assert_eq!(Staking::eras_validator_prefs(0, 11), new_prefs);
+
+        // Nothing is migrated twice
+        <Validators<Test>>::insert(&11, ValidatorPrefs::default());
+        migration::on_runtime_upgrade::<Test>();
+        assert_eq!(Staking::validators(&11), ValidatorPrefs::default());
+    });
+}
+
+#[test]
+fn offline_reports_are_migrated_from_version_4() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        use migration::deprecated::v4;
+
+        <v4::SlashCount<Test>>::insert(&11, 2);
+        <v4::SlashCount<Test>>::insert(&31, 1);
+        <v4::SlashCount<Test>>::insert(&41, 3);
+        <v4::RecentlyOffline<Test>>::put(vec![(11, 1, 2)]);
+        <StorageVersion<Test>>::put(4);
+
+        migration::on_runtime_upgrade::<Test>();
+        assert_eq!(Staking::offline_records(0, 11), 2);
+        assert_eq!(Staking::offline_records(0, 31), 1);
+        assert_eq!(Staking::slash_count(&11), 2);
+        assert!(!<v4::SlashCount<Test>>::exists(&11));
+        assert!(!<v4::RecentlyOffline<Test>>::exists());
+
+        // 41 is neither a validator nor elected, its old count is not carried over
+        assert_eq!(Staking::offline_records(0, 41), 0);
+        assert_eq!(Staking::slash_count(&41), 0);
+        assert!(<v4::SlashCount<Test>>::exists(&41));
+    });
+}
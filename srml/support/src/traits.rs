@@ -3,7 +3,7 @@ use sr_primitives::traits::{
     MaybeSerializeDebug, SimpleArithmetic
 };
 use rstd::{prelude::*, result};
-use srml_support::traits::{Imbalance, Currency};
+use srml_support::traits::{Imbalance, Currency, LockIdentifier, WithdrawReason};
 
 pub trait SystemCurrency<AccountId, Currency>
  where Currency: SimpleArithmetic + Codec + Copy + MaybeSerializeDebug + Default {
@@ -20,6 +20,20 @@ pub trait SystemCurrency<AccountId, Currency>
 //    fn system_refund(who: &AccountId, value: Self::CurrencyOf, system_imbalance: Self::NegativeImbalanceOf, acc_imbalance: Self::NegativeImbalanceOf);
 }
 
+// lock ring in a deposit for kton
+pub trait DepositRing<AccountId, Currency>: srml_support::traits::Currency<AccountId> {
+    // lock `value` of the free ring of `who` for `months` months in a new deposit
+    // the kton minted goes to the free balance of `who`, and is returned
+    fn deposit_ring(who: &AccountId, value: Currency, months: u32) -> result::Result<Self::Balance, &'static str>;
+}
+
+// the balance an account must keep because of its locks
+pub trait LockedBalance<AccountId>: Currency<AccountId> {
+    // the least free balance `who` must keep for `reason`, ignoring the lock `except`
+    // locks overlap, so this is the largest of them, or the vesting balance
+    fn locked_balance(who: &AccountId, except: LockIdentifier, reason: WithdrawReason) -> Self::Balance;
+}
+
 //pub trait LockRate {
 //    //TODO： ugly to use u64, ready for hacking
 //    //    type Balance: SimpleArithmetic + As<usize> + As<u64> + Codec + Copy + MaybeSerializeDebug + Default;
@@ -1,9 +1,11 @@
-
-//TODO: move this into lib.rs to be a inner mod
+// wrapping these imbalances in a private module is necessary to ensure absolute privacy
+// of the inner member.
 use super::{result, Imbalance, Trait, Zero, Saturating, StorageValue};
 use rstd::mem;
 
-
+/// Opaque, move-only struct with private fields that serves as a token denoting that
+/// kton has been minted without any equal and opposite accounting.
+#[must_use]
 pub struct PositiveImbalance<T: Trait>(T::Balance);
 
 impl<T: Trait> PositiveImbalance<T> {
@@ -12,6 +14,9 @@ impl<T: Trait> PositiveImbalance<T> {
     }
 }
 
+/// Opaque, move-only struct with private fields that serves as a token denoting that
+/// kton has been burned without any equal and opposite accounting.
+#[must_use]
 pub struct NegativeImbalance<T: Trait>(T::Balance);
 
 impl<T: Trait> NegativeImbalance<T> {
@@ -126,4 +131,4 @@ impl<T: Trait> Drop for NegativeImbalance<T> {
             |v| *v = v.saturating_sub(self.0)
         );
     }
-}
\ No newline at end of file
+}
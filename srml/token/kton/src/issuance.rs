@@ -0,0 +1,49 @@
+use super::{result, SimpleArithmetic, TryInto, TryFrom, U256};
+
+/// how much kton is minted for locking ring
+pub trait IssuanceCurve<Currency, Balance> {
+    /// kton minted for locking `value` ring for `months` months
+    fn compute(months: u32, value: Currency) -> result::Result<Balance, &'static str>;
+}
+
+// precision of the fixed-point growth factor
+const PRECISION: u128 = 1_000_000_000_000_000_000;
+
+/// the curve from RFC-0007
+/// kton = ring * ((67 / 66) ^ months - 1) / 1970
+/// e.g. 10,000 ring locked for 12 months mints about 1.0039 kton
+pub struct MonthlyCompoundCurve;
+
+impl<Currency, Balance> IssuanceCurve<Currency, Balance> for MonthlyCompoundCurve
+    where
+        Currency: SimpleArithmetic,
+        Balance: SimpleArithmetic,
+{
+    fn compute(months: u32, value: Currency) -> result::Result<Balance, &'static str> {
+        if months == 0 {
+            return Err("months must be at least 1");
+        }
+
+        let value: u128 = value.try_into().map_err(|_| "ring value too large")?;
+
+        // (67 / 66) ^ months, scaled by `PRECISION`
+        // it never goes beyond 2 * PRECISION for the months allowed
+        let mut growth = U256::from(PRECISION);
+        for _ in 0..months {
+            growth = growth
+                .checked_mul(U256::from(67))
+                .ok_or("kton issuance overflow")?
+                / U256::from(66);
+        }
+
+        let kton = U256::from(value)
+            .checked_mul(growth - U256::from(PRECISION))
+            .ok_or("kton issuance overflow")?
+            / (U256::from(PRECISION) * U256::from(1970));
+
+        if kton > U256::from(u128::max_value()) {
+            return Err("kton issuance overflow");
+        }
+        Balance::try_from(kton.as_u128()).map_err(|_| "kton issuance overflow")
+    }
+}
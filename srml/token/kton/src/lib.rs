@@ -3,27 +3,40 @@
 use parity_codec::{Codec, Decode, Encode};
 use primitives::traits::{
     CheckedAdd, CheckedSub, MaybeSerializeDebug, Member, Saturating, SimpleArithmetic,
-    StaticLookup, Zero, Bounded
+    StaticLookup, Zero, Bounded, SaturatedConversion
 };
 
 use rstd::prelude::*;
-use rstd::{cmp, result, convert::{ TryInto, TryFrom}};
+use rstd::{cmp, mem, result, convert::{ TryInto, TryFrom}};
 use srml_support::{decl_event, decl_module, decl_storage, Parameter, StorageMap, StorageValue, ensure};
 use srml_support::dispatch::Result;
 use srml_support::traits::{
     Currency, ExistenceRequirement, Imbalance, LockableCurrency, LockIdentifier,
-    OnUnbalanced, SignedImbalance, UpdateBalanceOutcome,
+    OnFreeBalanceZero, OnUnbalanced, ReservableCurrency, SignedImbalance, UpdateBalanceOutcome,
     WithdrawReason, WithdrawReasons,
 };
 use substrate_primitives::U256;
-use system::ensure_signed;
+use system::{ensure_root, ensure_signed};
 
 // customed
-use dsupport::traits::SystemCurrency;
+use dsupport::traits::{DepositRing, LockedBalance, SystemCurrency};
 mod imbalance;
 use imbalance::{NegativeImbalance, PositiveImbalance};
+mod issuance;
+pub use issuance::{IssuanceCurve, MonthlyCompoundCurve};
+mod migration;
+
+mod mock;
+mod tests;
 
 const DEPOSIT_ID: LockIdentifier = *b"lockkton";
+// timestamp is in seconds, a month is counted as 30 days
+const MONTH_IN_SECONDS: u32 = 30 * 24 * 60 * 60;
+// kton burned for an early withdrawal is this many times
+// what the remaining months would have minted
+const EARLY_WITHDRAW_PENALTY: u32 = 3;
+// `RewardPerShare` is scaled by this to keep the dividend of a large kton supply
+const REWARD_PER_SHARE_PRECISION: u128 = 1_000_000_000_000_000_000;
 
 /// Struct to encode the vesting schedule of an individual account.
 #[derive(Encode, Decode, Copy, Clone, PartialEq, Eq)]
@@ -61,10 +74,11 @@ pub struct BalanceLock<Balance, BlockNumber> {
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Encode, Decode, Default)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub struct IndividualDeposit<Currency, Moment> {
-    pub month: Moment,
+    /// unique among all the deposits ever made
+    pub id: u64,
+    pub month: u32,
     pub start_at: Moment,
     pub value: Currency,
-    pub claimed: bool,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Encode, Decode, Default)]
@@ -74,6 +88,16 @@ pub struct Deposit<Currency, Moment> {
     pub deposit_list: Vec<IndividualDeposit<Currency, Moment>>,
 }
 
+/// dividend of a ktoner, in ring scaled by `REWARD_PER_SHARE_PRECISION`
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Dividend {
+    /// `RewardPerShare` when it was last settled
+    pub reward_per_share: U256,
+    /// reward earned but not withdrawn yet
+    pub unclaimed: U256,
+}
+
 type CurrencyOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 pub type NegativeImbalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::NegativeImbalance;
 pub type PositiveImbalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::PositiveImbalance;
@@ -82,16 +106,21 @@ pub trait Trait: timestamp::Trait {
     type Balance: Parameter + Member + SimpleArithmetic + Codec + Default + Copy +
     MaybeSerializeDebug + From<Self::BlockNumber>;
 
-    type Currency: LockableCurrency<Self::AccountId, Moment=Self::BlockNumber>;
+    type Currency: LockableCurrency<Self::AccountId, Moment=Self::BlockNumber> + LockedBalance<Self::AccountId>;
 
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
 
     // kton
     type OnMinted: OnUnbalanced<PositiveImbalance<Self>>;
     type OnRemoval: OnUnbalanced<NegativeImbalance<Self>>;
+    /// called when the free kton of an account falls below the existential deposit
+    type OnFreeBalanceZero: OnFreeBalanceZero<Self::AccountId>;
 
     // ring
     type SystemRefund: OnUnbalanced<PositiveImbalanceOf<Self>>;
+
+    /// how much kton is minted for a deposit
+    type KtonIssuanceCurve: IssuanceCurve<CurrencyOf<Self>, Self::Balance>;
 }
 
 decl_event!(
@@ -102,14 +131,20 @@ decl_event!(
         Moment = < T as timestamp::Trait>::Moment,
     {
         /// lock ring for getting kton
-        /// Balance is for kton
-        /// Currency is for ring
-        NewDeposit(Moment, AccountId, Balance, Currency),
-        /// Transfer succeeded (from, to, value, fees).
-        TokenTransfer(AccountId, AccountId, Balance),
-        /// Claim Reward
-        RewardClaim(AccountId, Currency),
-        WithdrawDeposit(AccountId, Currency, Moment, bool),
+        /// (who, deposit id, ring locked, kton minted, months, expire at)
+        NewDeposit(AccountId, u64, Currency, Balance, u32, Moment),
+        /// Transfer succeeded (from, to, value, dividend epoch).
+        TokenTransfer(AccountId, AccountId, Balance, u64),
+        /// Claim Reward (who, ring claimed, dividend epoch)
+        RewardClaim(AccountId, Currency, u64),
+        /// withdraw ring from an expired deposit
+        /// (who, deposit id, ring unlocked, months)
+        WithdrawDeposit(AccountId, u64, Currency, u32),
+        /// withdraw ring from a deposit before it expires
+        /// (who, deposit id, ring unlocked, kton burned as penalty, months)
+        EarlyWithdrawDeposit(AccountId, u64, Currency, Balance, u32),
+        /// the free and reserved kton of an account were set by root
+        BalanceSet(AccountId, Balance, Balance),
     }
 );
 
@@ -118,11 +153,17 @@ decl_storage! {
 	trait Store for Module<T: Trait> as Kton {
 
 	    pub DepositLedger get(deposit_ledger): map T::AccountId => Option<Deposit<CurrencyOf<T>, T::Moment>>;
-
-		// reward you can get per kton
-		pub RewardPerShare get(reward_per_share): CurrencyOf<T>;
-		// reward already paid to each ktoner
-		pub RewardPaidOut get(reward_paid_out): map T::AccountId => i128;
+		// number of deposits ever made, the id of the next deposit
+		pub DepositCount get(deposit_count): u64;
+
+		// reward you can get per kton, scaled by `REWARD_PER_SHARE_PRECISION`
+		pub RewardPerShare get(reward_per_share): U256;
+		// number of rewards shared by ktoners so far
+		pub DividendEpoch get(dividend_epoch): u64;
+		// reward of each ktoner, settled whenever its balance changes
+		pub Dividends get(dividend): map T::AccountId => Dividend;
+		// `RewardPerShare` when the storage was migrated to version 1, see `migration`
+		MigratedRewardPerShare: U256;
 
 		pub SysAcc get(sys_acc) config(): T::AccountId;
 
@@ -132,9 +173,9 @@ decl_storage! {
 		pub SysRevenuePot get(system_revenue): map T::AccountId => CurrencyOf<T>;
 
         /// For Currency and LockableCurrency Trait
-		/// The total `units issued in the system.
-		// like `existential_deposit`, but always set to 0
-		pub MinimumBalance get(minimum_balance): T::Balance = 0.into();
+		/// The minimum amount of kton required to keep an account open.
+		/// The dust below it goes to `OnRemoval`.
+		pub ExistentialDeposit get(existential_deposit) config(): T::Balance;
 
 		pub TotalIssuance get(total_issuance) build(|config: &GenesisConfig<T>| {
 			config.balances.iter().fold(Zero::zero(), |acc: T::Balance, &(_, n)| acc + n)
@@ -166,6 +207,9 @@ decl_storage! {
 					})
 			}).collect::<Vec<_>>()
 		}): map T::AccountId => Option<VestingSchedule<T::Balance>>;
+
+		/// the version of the storage layout, see `migration`
+		StorageVersion build(|_| migration::CURRENT_VERSION): u32;
 	}
 	add_extra_genesis {
 		config(balances): Vec<(T::AccountId, T::Balance)>;
@@ -178,51 +222,15 @@ decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
         fn deposit_event<T>() = default;
 
-
-        fn deposit(origin, value: CurrencyOf<T>, months: T::Moment) {
-            ensure!(!months.is_zero() && months <= 36.into(), "months must be at least 1");
-            let transactor = ensure_signed(origin)?;
-            if <DepositLedger<T>>::exists(&transactor) {
-                return Err("Already deposited.");
-            }
-
-            let free_currency = T::Currency::free_balance(&transactor);
-            let value = value.min(free_currency);
-
-            let now = <timestamp::Module<T>>::now();
-
-            let individual_deposit = IndividualDeposit {month: months.clone(), start_at: now.clone(), value: value, claimed: false};
-            let deposit = Deposit {total: value, deposit_list: vec![individual_deposit]};
-
-            Self::update_deposit(&transactor, &deposit);
-
-            let kton_return = Self::compute_kton_balance(months, value).unwrap();
-            let positive_imbalance = Self::deposit_creating(&transactor, kton_return);
-            T::OnMinted::on_unbalanced(positive_imbalance);
-            Self::deposit_event(RawEvent::NewDeposit(now, transactor, kton_return, value));
+        fn on_initialize() {
+            migration::on_runtime_upgrade::<T>();
         }
 
-
-        fn deposit_extra(origin, additional_value: CurrencyOf<T>, months: T::Moment) {
-             ensure!(!months.is_zero() && months <= 36.into(), "months must be at least 1");
-             let transactor = ensure_signed(origin)?;
-             let mut deposit = Self::deposit_ledger(&transactor).ok_or("Use fn deposit instead.")?;
-
-             let now = <timestamp::Module<T>>::now();
-             let free_currency = T::Currency::free_balance(&transactor);
-
-             if let Some(extra) = free_currency.checked_sub(&deposit.total) {
-                 let extra = extra.min(additional_value);
-                 deposit.total += extra;
-                 let individual_deposit = IndividualDeposit {month: months.clone(), start_at: now.clone(), value: extra.clone(), claimed: false};
-                 deposit.deposit_list.push(individual_deposit);
-                 Self::update_deposit(&transactor, &deposit);
-
-                 let kton_return = Self::compute_kton_balance(months, extra).unwrap();
-                 let positive_imbalance = Self::deposit_creating(&transactor, kton_return);
-                 T::OnMinted::on_unbalanced(positive_imbalance);
-                 Self::deposit_event(RawEvent::NewDeposit(now, transactor, kton_return, extra));
-             }
+        /// lock ring for getting kton
+        /// every call appends a new deposit to the ledger
+        fn deposit(origin, value: CurrencyOf<T>, months: u32) {
+            let transactor = ensure_signed(origin)?;
+            <Self as DepositRing<_, _>>::deposit_ring(&transactor, value, months)?;
         }
 
 
@@ -241,10 +249,129 @@ decl_module! {
             let transactor = ensure_signed(origin)?;
             let value_can_withdraw = Self::reward_can_withdraw(&transactor);
             if !value_can_withdraw.is_zero() {
-                Self::update_reward_paid_out(&transactor, value_can_withdraw, false);
                 T::Currency::transfer(&Self::sys_acc(), &transactor, value_can_withdraw)?;
-                Self::deposit_event(RawEvent::RewardClaim(transactor, value_can_withdraw));
+                Self::take_reward(&transactor, value_can_withdraw);
+                Self::deposit_event(RawEvent::RewardClaim(transactor, value_can_withdraw, Self::dividend_epoch()));
+            }
+        }
+
+        /// unlock ring from all deposits which have expired
+        /// and remove them from the ledger
+        fn withdraw_deposit(origin) {
+            let transactor = ensure_signed(origin)?;
+            migration::migrate_deposit_ledger::<T>(&transactor);
+            let mut deposit = Self::deposit_ledger(&transactor).ok_or("not a depositor")?;
+
+            let now = <timestamp::Module<T>>::now();
+            let (expired, remaining): (Vec<_>, Vec<_>) = deposit.deposit_list
+                .into_iter()
+                .partition(|d| now >= Self::expire_time(d));
+            ensure!(!expired.is_empty(), "no deposit has expired");
+            deposit.deposit_list = remaining;
+
+            for individual_deposit in expired.iter() {
+                deposit.total = deposit.total.saturating_sub(individual_deposit.value);
+            }
+            Self::update_deposit(&transactor, &deposit);
+
+            for individual_deposit in expired {
+                Self::deposit_event(RawEvent::WithdrawDeposit(
+                    transactor.clone(),
+                    individual_deposit.id,
+                    individual_deposit.value,
+                    individual_deposit.month
+                ));
+            }
+        }
+
+        /// unlock ring from a deposit before it expires
+        /// the penalty in kton is burned from the free balance
+        fn withdraw_deposit_early(origin, #[compact] deposit_id: u64) {
+            let transactor = ensure_signed(origin)?;
+            migration::migrate_deposit_ledger::<T>(&transactor);
+            let mut deposit = Self::deposit_ledger(&transactor).ok_or("not a depositor")?;
+
+            let now = <timestamp::Module<T>>::now();
+            let index = deposit.deposit_list
+                .iter()
+                .position(|d| d.id == deposit_id)
+                .ok_or("no such deposit")?;
+            let (value, month, penalty) = {
+                let individual_deposit = &deposit.deposit_list[index];
+                ensure!(now < Self::expire_time(individual_deposit), "deposit has expired, use withdraw_deposit instead");
+
+                let passed_months = (now - individual_deposit.start_at.clone()) / T::Moment::from(MONTH_IN_SECONDS);
+                let passed_months: u32 = passed_months.try_into().map_err(|_| "months overflow")?;
+                let remaining_months = individual_deposit.month - passed_months;
+                let penalty = T::KtonIssuanceCurve::compute(remaining_months, individual_deposit.value)?
+                    .saturating_mul(T::Balance::from(EARLY_WITHDRAW_PENALTY));
+
+                (individual_deposit.value, individual_deposit.month, penalty)
+            };
+
+            if !penalty.is_zero() {
+                let imbalance = <Self as Currency<_>>::withdraw(
+                    &transactor,
+                    penalty,
+                    WithdrawReason::Fee,
+                    ExistenceRequirement::AllowDeath
+                )?;
+                T::OnRemoval::on_unbalanced(imbalance);
             }
+
+            deposit.deposit_list.remove(index);
+            deposit.total = deposit.total.saturating_sub(value);
+            Self::update_deposit(&transactor, &deposit);
+
+            Self::deposit_event(RawEvent::EarlyWithdrawDeposit(transactor, deposit_id, value, penalty, month));
+        }
+
+        /// set the free and reserved kton of an account
+        /// the difference is minted or burned
+        ///
+        /// the dispatch origin for this call is `root`
+        fn set_balance(origin,
+            who: <T::Lookup as StaticLookup>::Source,
+            #[compact] new_free: T::Balance,
+            #[compact] new_reserved: T::Balance
+        ) {
+            ensure_root(origin)?;
+            let who = T::Lookup::lookup(who)?;
+
+            // dividends are settled in `set_free_balance` and `set_reserved_balance`
+            let current_free = Self::free_balance(&who);
+            if new_free > current_free {
+                mem::drop(PositiveImbalance::<T>::new(new_free - current_free));
+            } else if new_free < current_free {
+                mem::drop(NegativeImbalance::<T>::new(current_free - new_free));
+            }
+            Self::set_free_balance(&who, new_free);
+
+            let current_reserved = Self::reserved_balance(&who);
+            if new_reserved > current_reserved {
+                mem::drop(PositiveImbalance::<T>::new(new_reserved - current_reserved));
+            } else if new_reserved < current_reserved {
+                mem::drop(NegativeImbalance::<T>::new(current_reserved - new_reserved));
+            }
+            Self::set_reserved_balance(&who, new_reserved);
+
+            Self::deposit_event(RawEvent::BalanceSet(who, new_free, new_reserved));
+        }
+
+        /// transfer kton on behalf of `source`
+        /// locks and the existential deposit are respected as in `transfer`
+        ///
+        /// the dispatch origin for this call is `root`
+        fn force_transfer(origin,
+            source: <T::Lookup as StaticLookup>::Source,
+            dest: <T::Lookup as StaticLookup>::Source,
+            #[compact] value: T::Balance
+        ) {
+            ensure_root(origin)?;
+            let source = T::Lookup::lookup(source)?;
+            let dest = T::Lookup::lookup(dest)?;
+
+            <Self as Currency<_>>::transfer(&source, &dest, value)?;
         }
 
 
@@ -256,7 +383,15 @@ decl_module! {
 
 impl<T: Trait> Module<T> {
 
+    /// update the deposit ledger and the ring lock
+    /// both of them are removed once nothing is locked
     fn update_deposit(who: &T::AccountId, deposit: &Deposit<CurrencyOf<T>, T::Moment>) {
+        if deposit.total.is_zero() {
+            T::Currency::remove_lock(DEPOSIT_ID, &who);
+            <DepositLedger<T>>::remove(who);
+            return;
+        }
+
         T::Currency::set_lock(
             DEPOSIT_ID,
             &who,
@@ -268,29 +403,26 @@ impl<T: Trait> Module<T> {
         <DepositLedger<T>>::insert(who, deposit);
     }
 
+    /// check that `total` ring can be locked for deposits on top of all the other locks
+    /// the deposit lock itself is replaced by the new total, so it is not counted
+    fn ensure_can_lock(who: &T::AccountId, total: CurrencyOf<T>) -> Result {
+        let new_balance = T::Currency::free_balance(who)
+            .checked_sub(&total)
+            .ok_or("not enough free ring to deposit")?;
+        ensure!(
+            new_balance >= T::Currency::locked_balance(who, DEPOSIT_ID, WithdrawReason::Transfer),
+            "account liquidity restrictions prevent withdrawal"
+        );
 
-    fn convert_to_paid_out(value: T::Balance) -> CurrencyOf<T> {
-        let value: u64 = value.try_into().unwrap_or_default() as u64;
-        let additional_reward_paid_out: CurrencyOf<T> = Self::reward_per_share() *  value.try_into().unwrap_or_default();
-        additional_reward_paid_out
+        Ok(())
     }
 
-    fn compute_kton_balance(months: T::Moment, value: CurrencyOf<T>) -> Option<T::Balance> {
-        let months = months.try_into().unwrap_or_default() as u64;
-        let value = value.try_into().unwrap_or_default() as u64;
-        if !months.is_zero() {
-            let no = U256::from(67_u128).pow(U256::from(months.clone())) * U256::exp10(6);
-            let de = U256::from(66_u128).pow(U256::from(months));
-
-            let res: U256 = U256::from(value) * no / de;
-            let value = (res - U256::exp10(6) * value) / (U256::from(197) * U256::exp10(7));
-            Some(value.as_u64().try_into().unwrap_or_default())
-        } else {
-            None
-        }
-
+    /// the moment when a deposit matures
+    fn expire_time(deposit: &IndividualDeposit<CurrencyOf<T>, T::Moment>) -> T::Moment {
+        deposit.start_at.clone() + T::Moment::from(deposit.month) * T::Moment::from(MONTH_IN_SECONDS)
     }
 
+
     pub fn vesting_balance(who: &T::AccountId) -> T::Balance {
         if let Some(v) = Self::vesting(who) {
             Self::free_balance(who)
@@ -301,29 +433,96 @@ impl<T: Trait> Module<T> {
     }
 
 
+    /// dividend of `who` including the reward not settled yet
+    fn settled_dividend(who: &T::AccountId) -> Dividend {
+        let mut dividend = migration::dividend_of::<T>(who);
+        let reward_per_share = Self::reward_per_share();
+        let balance = U256::from(Self::total_balance(who).saturated_into::<u128>());
+        dividend.unclaimed = dividend.unclaimed.saturating_add(
+            reward_per_share.saturating_sub(dividend.reward_per_share).saturating_mul(balance)
+        );
+        dividend.reward_per_share = reward_per_share;
+        dividend
+    }
+
+    /// settle the reward of `who` before its kton changes to `new_total`
+    fn settle_reward(who: &T::AccountId, new_total: T::Balance) {
+        let dividend = Self::settled_dividend(who);
+        Self::update_dividend(who, dividend, new_total);
+    }
+
+    /// mark `value` of the reward of `who` as withdrawn
+    fn take_reward(who: &T::AccountId, value: CurrencyOf<T>) {
+        let mut dividend = Self::settled_dividend(who);
+        let value = U256::from(value.saturated_into::<u128>())
+            .saturating_mul(U256::from(REWARD_PER_SHARE_PRECISION));
+        dividend.unclaimed = dividend.unclaimed.saturating_sub(value);
+        Self::update_dividend(who, dividend, Self::total_balance(who));
+    }
+
+    /// store the settled dividend of `who`, removed once there is neither reward nor kton
+    fn update_dividend(who: &T::AccountId, dividend: Dividend, total: T::Balance) {
+        migration::remove_reward_paid_out::<T>(who);
+        if dividend.unclaimed.is_zero() && total.is_zero() {
+            <Dividends<T>>::remove(who);
+        } else {
+            <Dividends<T>>::insert(who, dividend);
+        }
+    }
+
     // PRIVATE MUTABLE
     // NOTE: different from balacnes module
+    // the dividend is settled here, so every change of balance is covered
+    // it doesn't touch the account nonce, which belongs to ring
     fn set_free_balance(who: &T::AccountId, balance: T::Balance) -> UpdateBalanceOutcome {
-        //TODO: check the value of balance, but no ensure!(...)
+        Self::settle_reward(who, balance + Self::reserved_balance(who));
         <FreeBalance<T>>::insert(who, balance);
-        UpdateBalanceOutcome::Updated
+        if balance < Self::existential_deposit() {
+            Self::on_free_too_low(who);
+            UpdateBalanceOutcome::AccountKilled
+        } else {
+            UpdateBalanceOutcome::Updated
+        }
     }
 
+    // emptying the reserved balance is not dust, it neither kills the account nor is removed
     fn set_reserved_balance(who: &T::AccountId, balance: T::Balance) -> UpdateBalanceOutcome {
+        Self::settle_reward(who, Self::free_balance(who) + balance);
+        if balance.is_zero() {
+            <ReservedBalance<T>>::remove(who);
+            return UpdateBalanceOutcome::Updated;
+        }
+
         <ReservedBalance<T>>::insert(who, balance);
-        UpdateBalanceOutcome::Updated
+        if balance < Self::existential_deposit() {
+            Self::on_reserved_too_low(who);
+            UpdateBalanceOutcome::AccountKilled
+        } else {
+            UpdateBalanceOutcome::Updated
+        }
     }
 
-    /// update one's reward_paid_out
-    /// is_refund true -, means giving out reward
-    /// is_refund false +
-    fn update_reward_paid_out(who: &T::AccountId, value: CurrencyOf<T>, is_refund: bool) {
-        let value = i128::from(value.try_into().unwrap_or_default() as u64);
-        let reward_paid_out = Self::reward_paid_out(who);
-        if is_refund {
-            <RewardPaidOut<T>>::insert(who, reward_paid_out - value);
-        } else {
-            <RewardPaidOut<T>>::insert(who, reward_paid_out + value);
+    /// free kton is below the existential deposit
+    /// remove the dust and let other modules clean up
+    fn on_free_too_low(who: &T::AccountId) {
+        Self::settle_reward(who, Self::reserved_balance(who));
+        let dust = <FreeBalance<T>>::take(who);
+        <Locks<T>>::remove(who);
+
+        if !dust.is_zero() {
+            T::OnRemoval::on_unbalanced(NegativeImbalance::new(dust));
+        }
+
+        T::OnFreeBalanceZero::on_free_balance_zero(who);
+    }
+
+    /// reserved kton is below the existential deposit
+    fn on_reserved_too_low(who: &T::AccountId) {
+        Self::settle_reward(who, Self::free_balance(who));
+        let dust = <ReservedBalance<T>>::take(who);
+
+        if !dust.is_zero() {
+            T::OnRemoval::on_unbalanced(NegativeImbalance::new(dust));
         }
     }
 }
@@ -347,7 +546,7 @@ impl<T: Trait> Currency<T::AccountId> for Module<T> {
     }
 
     fn minimum_balance() -> Self::Balance {
-        Self::minimum_balance()
+        Self::existential_deposit()
     }
 
     fn free_balance(who: &T::AccountId) -> Self::Balance {
@@ -395,6 +594,9 @@ impl<T: Trait> Currency<T::AccountId> for Module<T> {
             None => return Err("balance too low to send value"),
             Some(b) => b,
         };
+        if to_balance.is_zero() && value < Self::existential_deposit() {
+            return Err("value too low to create account");
+        }
 
         Self::ensure_can_withdraw(transactor, value, WithdrawReason::Transfer, new_from_balance)?;
 
@@ -408,13 +610,7 @@ impl<T: Trait> Currency<T::AccountId> for Module<T> {
         if transactor != dest {
             Self::set_free_balance(transactor, new_from_balance);
             Self::set_free_balance(dest, new_to_balance);
-            // settle transactor reward
-            let from_should_withdraw = Self::reward_can_withdraw(transactor);
-            Self::update_reward_paid_out(transactor, from_should_withdraw, true);
-            // settle dest reward
-            let to_should_not_withdraw = Self::convert_to_paid_out(value);
-            Self::update_reward_paid_out(dest, to_should_not_withdraw, false);
-            Self::deposit_event(RawEvent:: TokenTransfer(transactor.clone(), dest.clone(), value));
+            Self::deposit_event(RawEvent::TokenTransfer(transactor.clone(), dest.clone(), value, Self::dividend_epoch()));
         }
 
         Ok(())
@@ -428,7 +624,7 @@ impl<T: Trait> Currency<T::AccountId> for Module<T> {
         liveness: ExistenceRequirement,
     ) -> result::Result<Self::NegativeImbalance, &'static str> {
         if let Some(new_balance) = Self::free_balance(who).checked_sub(&value) {
-            if liveness == ExistenceRequirement::KeepAlive && new_balance < Self::minimum_balance() {
+            if liveness == ExistenceRequirement::KeepAlive && new_balance < Self::existential_deposit() {
                 return Err("payment would kill account")
             }
             Self::ensure_can_withdraw(who, value, reason, new_balance)?;
@@ -467,8 +663,6 @@ impl<T: Trait> Currency<T::AccountId> for Module<T> {
         if Self::total_balance(who).is_zero() {
             return Err("beneficiary account must pre-exist");
         }
-        let additional_reward_paid_out = Self::convert_to_paid_out(value);
-        Self::update_reward_paid_out(who, additional_reward_paid_out, false);
         Self::set_free_balance(who, Self::free_balance(who) + value);
         Ok(PositiveImbalance::new(value))
     }
@@ -493,21 +687,22 @@ impl<T: Trait> Currency<T::AccountId> for Module<T> {
         UpdateBalanceOutcome
     ) {
         let original = Self::free_balance(who);
+        if balance < Self::existential_deposit() && original.is_zero() {
+            // nothing to create, and the dust would be removed
+            // before its positive imbalance is accounted
+            return (
+                SignedImbalance::Positive(Self::PositiveImbalance::zero()),
+                UpdateBalanceOutcome::AccountKilled,
+            )
+        }
 
         let imbalance = if original <= balance {
-            // update reward paid out
-            let additional_reward_paid_out = Self::convert_to_paid_out(balance);
-            Self::update_reward_paid_out(who, additional_reward_paid_out, false);
-
             SignedImbalance::Positive(PositiveImbalance::new(balance - original))
         } else {
             SignedImbalance::Negative(NegativeImbalance::new(original - balance))
         };
 
-        let outcome = {
-            Self::set_free_balance(who, balance);
-            UpdateBalanceOutcome::Updated
-        };
+        let outcome = Self::set_free_balance(who, balance);
 
         (imbalance, outcome)
     }
@@ -515,6 +710,68 @@ impl<T: Trait> Currency<T::AccountId> for Module<T> {
 }
 
 
+// dividends are settled by `set_free_balance` and `set_reserved_balance`
+// so moving reserved kton keeps the reward of both sides
+impl<T: Trait> ReservableCurrency<T::AccountId> for Module<T> {
+    fn can_reserve(who: &T::AccountId, value: Self::Balance) -> bool {
+        Self::free_balance(who)
+            .checked_sub(&value)
+            .map_or(false, |new_balance|
+                Self::ensure_can_withdraw(who, value, WithdrawReason::Reserve, new_balance).is_ok()
+            )
+    }
+
+    fn reserved_balance(who: &T::AccountId) -> Self::Balance {
+        <ReservedBalance<T>>::get(who)
+    }
+
+    fn reserve(who: &T::AccountId, value: Self::Balance) -> result::Result<(), &'static str> {
+        let b = Self::free_balance(who);
+        if b < value {
+            return Err("not enough free funds")
+        }
+        let new_balance = b - value;
+        Self::ensure_can_withdraw(who, value, WithdrawReason::Reserve, new_balance)?;
+        Self::set_reserved_balance(who, Self::reserved_balance(who) + value);
+        Self::set_free_balance(who, new_balance);
+        Ok(())
+    }
+
+    fn unreserve(who: &T::AccountId, value: Self::Balance) -> Self::Balance {
+        let b = Self::reserved_balance(who);
+        let actual = cmp::min(b, value);
+        Self::set_free_balance(who, Self::free_balance(who) + actual);
+        Self::set_reserved_balance(who, b - actual);
+        value - actual
+    }
+
+    fn slash_reserved(
+        who: &T::AccountId,
+        value: Self::Balance
+    ) -> (Self::NegativeImbalance, Self::Balance) {
+        let b = Self::reserved_balance(who);
+        let slash = cmp::min(b, value);
+        Self::set_reserved_balance(who, b - slash);
+        (NegativeImbalance::new(slash), value - slash)
+    }
+
+    fn repatriate_reserved(
+        slashed: &T::AccountId,
+        beneficiary: &T::AccountId,
+        value: Self::Balance,
+    ) -> result::Result<Self::Balance, &'static str> {
+        if Self::total_balance(beneficiary).is_zero() {
+            return Err("beneficiary account must pre-exist");
+        }
+        let b = Self::reserved_balance(slashed);
+        let slash = cmp::min(b, value);
+        Self::set_free_balance(beneficiary, Self::free_balance(beneficiary) + slash);
+        Self::set_reserved_balance(slashed, b - slash);
+        Ok(value - slash)
+    }
+}
+
+
 impl<T: Trait> LockableCurrency<T::AccountId> for Module<T>
     where
         T::Balance: MaybeSerializeDebug
@@ -600,11 +857,15 @@ impl<T: Trait> SystemCurrency<T::AccountId, CurrencyOf<T>> for Module<T> {
         let positive = T::Currency::deposit_creating(&sys_acc, value);
 
         // update reward-per-share
-        let total_issuance: u64 = Self::total_issuance().try_into().unwrap_or_default() as u64;
-        //TODO: if kton total_issuance is super high
-        // this will be zero
-        let additional_reward_per_share = value / total_issuance.try_into().unwrap_or_default();
-        <RewardPerShare<T>>::mutate(|r| *r += additional_reward_per_share);
+        // nobody shares the reward if there is no kton, it just stays in the pot
+        let total_issuance = Self::total_issuance().saturated_into::<u128>();
+        if total_issuance > 0 {
+            let additional_reward_per_share = U256::from(value.saturated_into::<u128>())
+                * U256::from(REWARD_PER_SHARE_PRECISION)
+                / U256::from(total_issuance);
+            <RewardPerShare<T>>::mutate(|r| *r = r.saturating_add(additional_reward_per_share));
+            <DividendEpoch<T>>::mutate(|e| *e += 1);
+        }
 
         <SysRevenuePot<T>>::insert(&sys_acc, Self::system_revenue(&sys_acc) + value);
 
@@ -615,16 +876,12 @@ impl<T: Trait> SystemCurrency<T::AccountId, CurrencyOf<T>> for Module<T> {
 
     // PUB IMMUTABLE
     fn reward_can_withdraw(who: &T::AccountId) -> CurrencyOf<T> {
-        let free_balance = Self::free_balance(who);
-        let max_should_withdraw = Self::convert_to_paid_out(free_balance);
-        let max_should_withdraw: u64  = max_should_withdraw.try_into().unwrap_or_default() as u64;
-        let should_withdraw = i128::from(max_should_withdraw) - Self::reward_paid_out(who);
-        if should_withdraw <= 0 {
-            0.into()
+        let should_withdraw = Self::settled_dividend(who).unclaimed / U256::from(REWARD_PER_SHARE_PRECISION);
+        if should_withdraw > U256::from(u128::max_value()) {
+            u128::max_value().saturated_into()
         } else {
-            u64::try_from(should_withdraw).unwrap_or_default().try_into().unwrap_or_default()
+            should_withdraw.as_u128().saturated_into()
         }
-
     }
 
     /// pay system fee with reward
@@ -641,12 +898,7 @@ impl<T: Trait> SystemCurrency<T::AccountId, CurrencyOf<T>> for Module<T> {
         let withdraw_value = value.min(can_withdraw_value);
 
         if withdraw_value > 0.into() {
-            let paid_out_new = match Self::reward_paid_out(who).checked_add(i128::from(withdraw_value.try_into().unwrap_or_default() as u64)) {
-                Some(v) => v,
-                None => return Err("wrong with paidout"),
-            };
-
-            <RewardPaidOut<T>>::insert(who, paid_out_new);
+            Self::take_reward(who, withdraw_value);
             system_imbalance = T::Currency::slash(&Self::sys_acc(), withdraw_value).0;
         }
 
@@ -666,3 +918,34 @@ impl<T: Trait> SystemCurrency<T::AccountId, CurrencyOf<T>> for Module<T> {
 
 }
 
+
+impl<T: Trait> DepositRing<T::AccountId, CurrencyOf<T>> for Module<T> {
+    // also used by staking to lock the ring rewards of `RewardDestination::Staked`
+    fn deposit_ring(who: &T::AccountId, value: CurrencyOf<T>, months: u32) -> result::Result<T::Balance, &'static str> {
+        ensure!(months >= 1 && months <= 36, "months must be between 1 and 36");
+        ensure!(!value.is_zero(), "deposit value must be positive");
+
+        migration::migrate_deposit_ledger::<T>(who);
+        let mut deposit = Self::deposit_ledger(who).unwrap_or_default();
+        let total = deposit.total.checked_add(&value).ok_or("deposit total overflow")?;
+        Self::ensure_can_lock(who, total)?;
+        let kton_return = T::KtonIssuanceCurve::compute(months, value)?;
+
+        let now = <timestamp::Module<T>>::now();
+        let id = Self::deposit_count();
+
+        let individual_deposit = IndividualDeposit {id, month: months, start_at: now, value: value};
+        let expire_at = Self::expire_time(&individual_deposit);
+        deposit.total = total;
+        deposit.deposit_list.push(individual_deposit);
+
+        Self::update_deposit(who, &deposit);
+        <DepositCount<T>>::put(id + 1);
+
+        let positive_imbalance = Self::deposit_creating(who, kton_return);
+        T::OnMinted::on_unbalanced(positive_imbalance);
+        Self::deposit_event(RawEvent::NewDeposit(who.clone(), id, value, kton_return, months, expire_at));
+
+        Ok(kton_return)
+    }
+}
@@ -0,0 +1,129 @@
+//! storage migrations of the kton module
+//!
+//! the storage version is bumped on the first block of a runtime which bumps `CURRENT_VERSION`
+//! the maps of this module can't be enumerated, so the accounts are migrated lazily:
+//! a deposit ledger in an old layout is converted by the first call which touches it,
+//! and an old reward is settled into `Dividends` by the first change of the dividend
+
+use super::*;
+
+/// the current version of the storage layout, new chains start with it
+pub const CURRENT_VERSION: u32 = 1;
+
+/// apply all the migrations the storage has not gone through yet
+pub fn on_runtime_upgrade<T: Trait>() {
+    let version = <StorageVersion<T>>::get();
+    if version >= CURRENT_VERSION {
+        return;
+    }
+
+    if version < 1 {
+        migrate_reward_per_share::<T>();
+    }
+
+    <StorageVersion<T>>::put(CURRENT_VERSION);
+}
+
+/// the storage items whose layout changed, as they were before each version
+pub(crate) mod deprecated {
+    pub mod v0 {
+        use super::super::*;
+
+        /// `IndividualDeposit` before version 1
+        #[derive(Encode, Decode, Clone)]
+        pub struct IndividualDeposit<Currency, Moment> {
+            pub month: Moment,
+            pub start_at: Moment,
+            pub value: Currency,
+            pub claimed: bool,
+        }
+
+        /// `Deposit` before version 1
+        #[derive(Encode, Decode, Clone)]
+        pub struct Deposit<Currency, Moment> {
+            pub total: Currency,
+            pub deposit_list: Vec<IndividualDeposit<Currency, Moment>>,
+        }
+
+        pub struct Module<T: Trait>(rstd::marker::PhantomData<T>);
+
+        decl_storage! {
+            trait Store for Module<T: Trait> as Kton {
+                pub DepositLedger: map T::AccountId => Option<Deposit<CurrencyOf<T>, T::Moment>>;
+                pub RewardPerShare: CurrencyOf<T>;
+                pub RewardPaidOut: map T::AccountId => i128;
+            }
+        }
+    }
+}
+
+/// the deposit ledger of `who`, converted to the current layout if needed
+///
+/// before version 1 the months of a deposit were a `Moment` and the deposits had no id,
+/// while withdrawn deposits were kept in the ledger
+/// the deposits are given ids from `DepositCount`, so they are unique among the deposits made
+/// since and the ones of the ledgers converted before, while the ones already withdrawn are dropped
+pub fn migrate_deposit_ledger<T: Trait>(who: &T::AccountId) {
+    // the new layout is longer, so an old ledger with deposits never decodes as a new one
+    if <DepositLedger<T>>::get(who).is_some() {
+        return;
+    }
+    let old = match <deprecated::v0::DepositLedger<T>>::get(who) {
+        Some(old) => old,
+        None => return,
+    };
+
+    let mut id = <Module<T>>::deposit_count();
+    let mut deposit = Deposit { total: Zero::zero(), deposit_list: vec![] };
+    for individual_deposit in old.deposit_list.into_iter().filter(|d| !d.claimed) {
+        deposit.total = deposit.total.saturating_add(individual_deposit.value);
+        deposit.deposit_list.push(IndividualDeposit {
+            id,
+            // deposits were never longer than 36 months
+            month: individual_deposit.month.try_into().unwrap_or(36),
+            start_at: individual_deposit.start_at,
+            value: individual_deposit.value,
+        });
+        id += 1;
+    }
+
+    <DepositCount<T>>::put(id);
+    <Module<T>>::update_deposit(who, &deposit);
+}
+
+/// `RewardPerShare` was a plain ring amount, it is now scaled by `REWARD_PER_SHARE_PRECISION`
+fn migrate_reward_per_share<T: Trait>() {
+    let reward_per_share = U256::from(<deprecated::v0::RewardPerShare<T>>::get().saturated_into::<u128>())
+        .saturating_mul(U256::from(REWARD_PER_SHARE_PRECISION));
+    <RewardPerShare<T>>::put(reward_per_share);
+    <MigratedRewardPerShare<T>>::put(reward_per_share);
+}
+
+/// the dividend of `who`, with the reward of `RewardPaidOut` if it was not settled yet
+///
+/// before version 1 the reward of an account was `RewardPerShare * free balance - RewardPaidOut`
+/// it is carried over to `unclaimed` as of the migration, the free balance has not changed since
+/// accounts without `RewardPaidOut` need nothing, their default dividend settles the same reward
+pub fn dividend_of<T: Trait>(who: &T::AccountId) -> Dividend {
+    if !<deprecated::v0::RewardPaidOut<T>>::exists(who) {
+        return <Module<T>>::dividend(who);
+    }
+
+    let reward_per_share = <MigratedRewardPerShare<T>>::get();
+    let reward = reward_per_share
+        .saturating_mul(U256::from(<Module<T>>::free_balance(who).saturated_into::<u128>()));
+    let paid_out = <deprecated::v0::RewardPaidOut<T>>::get(who);
+    let paid_out_scaled = |paid_out: u128| U256::from(paid_out).saturating_mul(U256::from(REWARD_PER_SHARE_PRECISION));
+    let unclaimed = if paid_out >= 0 {
+        reward.saturating_sub(paid_out_scaled(paid_out as u128))
+    } else {
+        reward.saturating_add(paid_out_scaled(paid_out.wrapping_neg() as u128))
+    };
+
+    Dividend { reward_per_share, unclaimed }
+}
+
+/// the reward of `RewardPaidOut` is settled once the dividend of `who` is written
+pub fn remove_reward_paid_out<T: Trait>(who: &T::AccountId) {
+    <deprecated::v0::RewardPaidOut<T>>::remove(who);
+}
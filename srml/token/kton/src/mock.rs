@@ -0,0 +1,148 @@
+//! Test utilities
+
+#![cfg(test)]
+
+use std::cell::RefCell;
+use primitives::BuildStorage;
+use primitives::traits::IdentityLookup;
+use primitives::testing::Header;
+use substrate_primitives::{H256, Blake2Hasher};
+use runtime_io;
+use srml_support::{impl_outer_origin, traits::{Imbalance, OnFreeBalanceZero, OnUnbalanced}};
+use crate::{GenesisConfig, Module, Trait, MonthlyCompoundCurve, NegativeImbalance};
+
+pub type AccountId = u64;
+pub type Balance = u64;
+
+// the account holding system revenue
+pub const SYS_ACC: AccountId = 42;
+
+thread_local! {
+    static REAPED: RefCell<Vec<AccountId>> = RefCell::new(vec![]);
+    static REMOVED: RefCell<Balance> = RefCell::new(0);
+}
+
+/// records the accounts whose free kton has been reaped
+pub struct RecordReaped;
+impl OnFreeBalanceZero<AccountId> for RecordReaped {
+    fn on_free_balance_zero(who: &AccountId) {
+        REAPED.with(|r| r.borrow_mut().push(*who));
+    }
+}
+
+pub fn reaped() -> Vec<AccountId> {
+    REAPED.with(|r| r.borrow().clone())
+}
+
+/// records the kton burned through `OnRemoval`
+pub struct RecordRemoval;
+impl OnUnbalanced<NegativeImbalance<Test>> for RecordRemoval {
+    fn on_unbalanced(amount: NegativeImbalance<Test>) {
+        REMOVED.with(|r| *r.borrow_mut() += amount.peek());
+    }
+}
+
+pub fn removed() -> Balance {
+    REMOVED.with(|r| *r.borrow())
+}
+
+impl_outer_origin!{
+    pub enum Origin for Test {}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test;
+impl system::Trait for Test {
+    type Origin = Origin;
+    type Index = u64;
+    type BlockNumber = u64;
+    type Hash = H256;
+    type Hashing = ::primitives::traits::BlakeTwo256;
+    type AccountId = AccountId;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Header = Header;
+    type Event = ();
+}
+
+impl timestamp::Trait for Test {
+    type Moment = u64;
+    type OnTimestampSet = ();
+}
+
+impl ring::Trait for Test {
+    type Balance = Balance;
+    type OnFreeBalanceZero = ();
+    type OnNewAccount = ();
+    type Event = ();
+    type TransactionPayment = ();
+    type TransferPayment = ();
+    type DustRemoval = ();
+}
+
+impl Trait for Test {
+    type Balance = Balance;
+    type Currency = Ring;
+    type Event = ();
+    type OnMinted = ();
+    type OnRemoval = RecordRemoval;
+    type OnFreeBalanceZero = RecordReaped;
+    type SystemRefund = ();
+    type KtonIssuanceCurve = MonthlyCompoundCurve;
+}
+
+pub struct ExtBuilder {
+    existential_deposit: Balance,
+    kton_balances: Vec<(AccountId, Balance)>,
+}
+
+impl Default for ExtBuilder {
+    fn default() -> Self {
+        Self {
+            existential_deposit: 0,
+            kton_balances: vec![(1, 100), (2, 200), (3, 300), (4, 400)],
+        }
+    }
+}
+
+impl ExtBuilder {
+    pub fn existential_deposit(mut self, existential_deposit: Balance) -> Self {
+        self.existential_deposit = existential_deposit;
+        self
+    }
+    pub fn kton_balances(mut self, balances: Vec<(AccountId, Balance)>) -> Self {
+        self.kton_balances = balances;
+        self
+    }
+    pub fn build(self) -> runtime_io::TestExternalities<Blake2Hasher> {
+        let (mut t, mut c) = system::GenesisConfig::<Test>::default().build_storage().unwrap();
+        let _ = ring::GenesisConfig::<Test>{
+            balances: vec![
+                (1, 1_000_000_000_000),
+                (2, 2_000_000_000_000),
+                (3, 3_000_000_000_000),
+                (4, 4_000_000_000_000),
+            ],
+            transaction_base_fee: 0,
+            transaction_byte_fee: 0,
+            existential_deposit: 0,
+            transfer_fee: 0,
+            creation_fee: 0,
+            vesting: vec![],
+        }.assimilate_storage(&mut t, &mut c);
+        let _ = GenesisConfig::<Test>{
+            balances: self.kton_balances,
+            vesting: vec![],
+            sys_acc: SYS_ACC,
+            existential_deposit: self.existential_deposit,
+        }.assimilate_storage(&mut t, &mut c);
+        let _ = timestamp::GenesisConfig::<Test>{
+            minimum_period: 5,
+        }.assimilate_storage(&mut t, &mut c);
+        t.into()
+    }
+}
+
+pub type Ring = ring::Module<Test>;
+pub type Kton = Module<Test>;
+pub type Timestamp = timestamp::Module<Test>;
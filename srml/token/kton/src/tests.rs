@@ -0,0 +1,597 @@
+#![cfg(test)]
+
+use super::*;
+use mock::{AccountId, ExtBuilder, Kton, Origin, Ring, SYS_ACC, Test, Timestamp, reaped, removed};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use runtime_io::with_externalities;
+use srml_support::assert_ok;
+
+// ring and kton both have 9 decimals here
+const COIN: u128 = 1_000_000_000;
+
+fn compute(months: u32, value: u128) -> result::Result<u128, &'static str> {
+    <MonthlyCompoundCurve as IssuanceCurve<u128, u128>>::compute(months, value)
+}
+
+#[test]
+fn issuance_curve_matches_rfc_table() {
+    // 10,000 ring locked for
+    let value = 10_000 * COIN;
+    assert_eq!(compute(1, value), Ok(76_911_244));
+    assert_eq!(compute(3, value), Ok(234_247_355));
+    assert_eq!(compute(6, value), Ok(479_304_459));
+    assert_eq!(compute(12, value), Ok(1_003_866_274));
+    assert_eq!(compute(24, value), Ok(2_206_258_805));
+    assert_eq!(compute(36, value), Ok(3_646_438_475));
+}
+
+#[test]
+fn issuance_curve_is_linear_in_value() {
+    assert_eq!(compute(12, COIN), Ok(100_386));
+    assert_eq!(compute(12, 0), Ok(0));
+    // no truncation to u64 for large deposits
+    let whale = 10_000_000_000 * COIN;
+    assert_eq!(compute(12, whale), Ok(1_003_866_274_480_715));
+}
+
+#[test]
+fn issuance_curve_reports_errors() {
+    assert_eq!(compute(0, COIN), Err("months must be at least 1"));
+    assert_eq!(
+        <MonthlyCompoundCurve as IssuanceCurve<u128, u64>>::compute(36, u128::max_value()),
+        Err("kton issuance overflow")
+    );
+}
+
+#[test]
+fn reward_is_shared_by_kton_holders() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        // 1000 kton in total
+        Kton::reward_to_pot(1000);
+        assert_eq!(Kton::reward_can_withdraw(&1), 100);
+        assert_eq!(Kton::reward_can_withdraw(&4), 400);
+
+        // reward earned before a transfer stays with the sender
+        assert_ok!(<Kton as Currency<_>>::transfer(&1, &2, 100));
+        assert_eq!(Kton::reward_can_withdraw(&1), 100);
+        assert_eq!(Kton::reward_can_withdraw(&2), 200);
+
+        Kton::reward_to_pot(1000);
+        assert_eq!(Kton::reward_can_withdraw(&1), 100);
+        assert_eq!(Kton::reward_can_withdraw(&2), 500);
+
+        let ring_before = Ring::free_balance(&2);
+        assert_ok!(Kton::claim_reward(Origin::signed(2)));
+        assert_eq!(Ring::free_balance(&2), ring_before + 500);
+        assert_eq!(Kton::reward_can_withdraw(&2), 0);
+        assert_eq!(Ring::free_balance(&SYS_ACC), 1500);
+    });
+}
+
+#[test]
+fn reward_does_not_round_to_zero_for_large_supply() {
+    with_externalities(&mut ExtBuilder::default()
+        .kton_balances(vec![(1, 1_000_000_000_000_000_000), (2, 3_000_000_000_000_000_000)])
+        .build(), ||
+    {
+        Kton::reward_to_pot(1000);
+        assert_eq!(Kton::reward_can_withdraw(&1), 250);
+        assert_eq!(Kton::reward_can_withdraw(&2), 750);
+    });
+}
+
+#[test]
+fn newly_minted_kton_does_not_share_past_reward() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        Kton::reward_to_pot(1000);
+        let _ = Kton::deposit_creating(&5, 1000);
+        let _ = Kton::make_free_balance_be(&1, 1100);
+        assert_eq!(Kton::reward_can_withdraw(&5), 0);
+        assert_eq!(Kton::reward_can_withdraw(&1), 100);
+
+        // 3000 kton in total now
+        Kton::reward_to_pot(3000);
+        assert_eq!(Kton::reward_can_withdraw(&5), 1000);
+        assert_eq!(Kton::reward_can_withdraw(&1), 1200);
+    });
+}
+
+#[test]
+fn dividends_are_conserved() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        let accounts: Vec<AccountId> = vec![1, 2, 3, 4, 5];
+        let total_claimable = |accounts: &Vec<AccountId>| -> u64 {
+            accounts.iter().map(|who| Kton::reward_can_withdraw(who)).sum()
+        };
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut distributed = 0;
+        let mut distributions = 0;
+        let mut claimed = 0;
+        for _ in 0..1000 {
+            let who = accounts[rng.gen_range(0, accounts.len())];
+            let other = accounts[rng.gen_range(0, accounts.len())];
+            match rng.gen_range(0, 6) {
+                0 => {
+                    let value = rng.gen_range(1, 10_000);
+                    Kton::reward_to_pot(value);
+                    distributed += value;
+                    distributions += 1;
+                },
+                1 => {
+                    let value = rng.gen_range(0, Kton::free_balance(&who) + 1);
+                    assert_ok!(<Kton as Currency<_>>::transfer(&who, &other, value));
+                },
+                2 => {
+                    let _ = Kton::deposit_creating(&who, rng.gen_range(0, 1000));
+                },
+                3 => {
+                    let _ = Kton::slash(&who, rng.gen_range(0, 1000));
+                },
+                4 => {
+                    let _ = Kton::make_free_balance_be(&who, rng.gen_range(0, 1000));
+                },
+                _ => {
+                    let reward = Kton::reward_can_withdraw(&who);
+                    assert_ok!(Kton::claim_reward(Origin::signed(who)));
+                    claimed += reward;
+                },
+            }
+
+            assert!(claimed + total_claimable(&accounts) <= distributed);
+            assert_eq!(Ring::free_balance(&SYS_ACC), distributed - claimed);
+        }
+
+        // rounding loses at most one unit per holder for each distribution
+        let lost = distributed - claimed - total_claimable(&accounts);
+        assert!(lost <= distributions * accounts.len() as u64);
+    });
+}
+
+#[test]
+fn reserve_and_unreserve_keep_reward() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        assert_ok!(Kton::reserve(&1, 60));
+        assert_eq!(Kton::free_balance(&1), 40);
+        assert_eq!(<Kton as ReservableCurrency<_>>::reserved_balance(&1), 60);
+        assert!(!Kton::can_reserve(&1, 41));
+        assert_eq!(Kton::total_issuance(), 1000);
+
+        // reserved kton still shares the reward
+        Kton::reward_to_pot(1000);
+        assert_eq!(Kton::reward_can_withdraw(&1), 100);
+
+        assert_eq!(Kton::unreserve(&1, 100), 40);
+        assert_eq!(Kton::free_balance(&1), 100);
+        assert_eq!(Kton::reward_can_withdraw(&1), 100);
+    });
+}
+
+#[test]
+fn slash_and_repatriate_reserved_settle_reward() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        assert_ok!(Kton::reserve(&1, 100));
+        Kton::reward_to_pot(1000);
+
+        assert_eq!(Kton::repatriate_reserved(&1, &2, 60), Ok(0));
+        assert_eq!(Kton::free_balance(&2), 260);
+        assert_eq!(Kton::reward_can_withdraw(&1), 100);
+        assert_eq!(Kton::reward_can_withdraw(&2), 200);
+        assert_eq!(Kton::repatriate_reserved(&1, &5, 10), Err("beneficiary account must pre-exist"));
+
+        let (imbalance, remaining) = Kton::slash_reserved(&1, 50);
+        assert_eq!((imbalance.peek(), remaining), (40, 10));
+        drop(imbalance);
+        assert_eq!(Kton::total_issuance(), 960);
+
+        // 960 kton in total now
+        Kton::reward_to_pot(960);
+        assert_eq!(Kton::reward_can_withdraw(&1), 100);
+        assert_eq!(Kton::reward_can_withdraw(&2), 460);
+    });
+}
+
+#[test]
+fn dust_accounts_are_reaped() {
+    with_externalities(&mut ExtBuilder::default().existential_deposit(10).build(), || {
+        assert_eq!(
+            <Kton as Currency<_>>::transfer(&1, &5, 5),
+            Err("value too low to create account")
+        );
+
+        Kton::reward_to_pot(1000);
+        assert_ok!(<Kton as Currency<_>>::transfer(&1, &2, 95));
+        assert!(!<FreeBalance<Test>>::exists(&1));
+        assert_eq!(reaped(), vec![1]);
+        // the dust is burned
+        assert_eq!(Kton::total_issuance(), 995);
+        // while the reward earned is kept
+        assert_eq!(Kton::reward_can_withdraw(&1), 100);
+        assert_ok!(Kton::claim_reward(Origin::signed(1)));
+        assert!(!<Dividends<Test>>::exists(&1));
+    });
+}
+
+#[test]
+fn total_issuance_is_sum_of_balances() {
+    with_externalities(&mut ExtBuilder::default().existential_deposit(10).build(), || {
+        let accounts: Vec<AccountId> = vec![1, 2, 3, 4, 5];
+        let total_balance = |accounts: &Vec<AccountId>| -> u64 {
+            accounts.iter().map(|who| Kton::free_balance(who) + Kton::reserved_balance(who)).sum()
+        };
+        assert_eq!(total_balance(&accounts), Kton::total_issuance());
+
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..1000 {
+            let who = accounts[rng.gen_range(0, accounts.len())];
+            let other = accounts[rng.gen_range(0, accounts.len())];
+            let value = rng.gen_range(0, 1000);
+            // failed operations must not touch the issuance either
+            match rng.gen_range(0, 10) {
+                0 => {
+                    let ring = rng.gen_range(1_000_000_000, 100_000_000_000);
+                    let _ = Kton::deposit(Origin::signed(who), ring, rng.gen_range(1, 37));
+                },
+                1 => {
+                    let _ = Kton::withdraw_deposit_early(Origin::signed(who), rng.gen_range(0, Kton::deposit_count() + 1));
+                },
+                2 => {
+                    let _ = <Kton as Currency<_>>::transfer(&who, &other, value);
+                },
+                3 => {
+                    let _ = Kton::reserve(&who, value);
+                },
+                4 => {
+                    let _ = Kton::unreserve(&who, value);
+                },
+                5 => {
+                    let _ = Kton::slash(&who, value);
+                },
+                6 => {
+                    let _ = Kton::slash_reserved(&who, value);
+                },
+                7 => {
+                    let _ = Kton::repatriate_reserved(&who, &other, value);
+                },
+                8 => {
+                    let _ = Kton::make_free_balance_be(&who, value);
+                },
+                _ => {
+                    let _ = Kton::deposit_creating(&who, value);
+                },
+            }
+
+            assert_eq!(total_balance(&accounts), Kton::total_issuance());
+        }
+    });
+}
+
+#[test]
+fn set_balance_requires_root_and_keeps_reward() {
+    with_externalities(&mut ExtBuilder::default().existential_deposit(10).build(), || {
+        assert!(Kton::set_balance(Origin::signed(1), 1, 0, 0).is_err());
+
+        Kton::reward_to_pot(1000);
+        assert_ok!(Kton::set_balance(Origin::ROOT, 1, 500, 50));
+        assert_eq!(Kton::free_balance(&1), 500);
+        assert_eq!(Kton::reserved_balance(&1), 50);
+        assert_eq!(Kton::total_issuance(), 1450);
+        // the reward earned before is kept
+        assert_eq!(Kton::reward_can_withdraw(&1), 100);
+
+        // the new balance earns from now on
+        Kton::reward_to_pot(1450);
+        assert_eq!(Kton::reward_can_withdraw(&1), 650);
+
+        // below the existential deposit the account is reaped
+        assert_ok!(Kton::set_balance(Origin::ROOT, 1, 5, 0));
+        assert!(!<FreeBalance<Test>>::exists(&1));
+        assert_eq!(Kton::total_issuance(), 900);
+        assert_eq!(Kton::reward_can_withdraw(&1), 650);
+    });
+}
+
+#[test]
+fn force_transfer_requires_root() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        assert!(Kton::force_transfer(Origin::signed(2), 1, 2, 50).is_err());
+
+        assert_ok!(Kton::force_transfer(Origin::ROOT, 1, 2, 50));
+        assert_eq!(Kton::free_balance(&1), 50);
+        assert_eq!(Kton::free_balance(&2), 250);
+        assert_eq!(Kton::total_issuance(), 1000);
+
+        assert_eq!(
+            Kton::force_transfer(Origin::ROOT, 1, 2, 51),
+            Err("balance too low to send value")
+        );
+    });
+}
+
+#[test]
+fn deposit_ids_are_stable() {
+    // enough kton to pay the early withdrawal penalties
+    let kton_balances = vec![(1, 1_000_000_000), (2, 1_000_000_000)];
+    with_externalities(&mut ExtBuilder::default().kton_balances(kton_balances).build(), || {
+        let value = 100 * COIN as u64;
+        assert_ok!(Kton::deposit(Origin::signed(1), value, 12));
+        assert_ok!(Kton::deposit(Origin::signed(2), value, 12));
+        assert_ok!(Kton::deposit(Origin::signed(1), value, 36));
+        assert_eq!(Kton::deposit_count(), 3);
+
+        let ids = |who: AccountId| -> Vec<u64> {
+            Kton::deposit_ledger(&who).unwrap().deposit_list.iter().map(|d| d.id).collect()
+        };
+        assert_eq!(ids(1), vec![0, 2]);
+        assert_eq!(ids(2), vec![1]);
+
+        // deposits are found by id rather than by position
+        assert_eq!(
+            Kton::withdraw_deposit_early(Origin::signed(1), 1),
+            Err("no such deposit")
+        );
+        assert_ok!(Kton::withdraw_deposit_early(Origin::signed(1), 2));
+        assert_eq!(Kton::deposit_ledger(&1).unwrap().total, value);
+
+        assert_ok!(Kton::withdraw_deposit_early(Origin::signed(1), 0));
+        assert!(Kton::deposit_ledger(&1).is_none());
+
+        // ids are never reused
+        assert_ok!(Kton::deposit(Origin::signed(1), value, 1));
+        assert_eq!(ids(1), vec![3]);
+    });
+}
+
+#[test]
+fn withdraw_deposit_unlocks_matured_deposits() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        let value = 100 * COIN as u64;
+        let locked = || -> Vec<u64> { Ring::locks(&1).iter().map(|l| l.amount).collect() };
+        assert_ok!(Kton::deposit(Origin::signed(1), value, 1));
+        assert_ok!(Kton::deposit(Origin::signed(1), value, 3));
+        assert_eq!(locked(), vec![2 * value]);
+
+        assert_eq!(Kton::withdraw_deposit(Origin::signed(1)), Err("no deposit has expired"));
+        Timestamp::set_timestamp(MONTH_IN_SECONDS as u64 - 1);
+        assert_eq!(Kton::withdraw_deposit(Origin::signed(1)), Err("no deposit has expired"));
+
+        // the first deposit matures after a month, and is removed once withdrawn
+        Timestamp::set_timestamp(MONTH_IN_SECONDS as u64);
+        assert_ok!(Kton::withdraw_deposit(Origin::signed(1)));
+        let deposit = Kton::deposit_ledger(&1).unwrap();
+        assert_eq!(deposit.total, value);
+        assert_eq!(deposit.deposit_list.iter().map(|d| d.id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(locked(), vec![value]);
+        assert_eq!(Kton::withdraw_deposit(Origin::signed(1)), Err("no deposit has expired"));
+
+        Timestamp::set_timestamp(3 * MONTH_IN_SECONDS as u64);
+        assert_eq!(
+            Kton::withdraw_deposit_early(Origin::signed(1), 1),
+            Err("deposit has expired, use withdraw_deposit instead")
+        );
+        assert_ok!(Kton::withdraw_deposit(Origin::signed(1)));
+        assert!(Kton::deposit_ledger(&1).is_none());
+        assert!(locked().is_empty());
+        assert_eq!(Kton::withdraw_deposit(Origin::signed(1)), Err("not a depositor"));
+    });
+}
+
+#[test]
+fn early_withdrawal_burns_a_penalty() {
+    let kton_balances = vec![(1, 1_000_000_000)];
+    with_externalities(&mut ExtBuilder::default().kton_balances(kton_balances).build(), || {
+        let value = 100 * COIN as u64;
+        let locked = |who| -> Vec<u64> { Ring::locks(&who).iter().map(|l| l.amount).collect() };
+        assert_ok!(Kton::deposit(Origin::signed(1), value, 12));
+        assert_ok!(Kton::deposit(Origin::signed(1), value, 12));
+        let kton_before = Kton::free_balance(&1);
+        let issuance_before = Kton::total_issuance();
+
+        // 10 months remain, the penalty is three times what they would have minted
+        Timestamp::set_timestamp(2 * MONTH_IN_SECONDS as u64 + 1);
+        let penalty = <MonthlyCompoundCurve as IssuanceCurve<u64, u64>>::compute(10, value).unwrap()
+            * EARLY_WITHDRAW_PENALTY as u64;
+        assert_ok!(Kton::withdraw_deposit_early(Origin::signed(1), 0));
+        assert_eq!(Kton::free_balance(&1), kton_before - penalty);
+        assert_eq!(removed(), penalty);
+        assert_eq!(Kton::total_issuance(), issuance_before - penalty);
+
+        // only the ring of the other deposit stays locked
+        assert_eq!(Kton::deposit_ledger(&1).unwrap().total, value);
+        assert_eq!(locked(1), vec![value]);
+
+        // the kton minted by a deposit does not cover its penalty
+        assert_ok!(Kton::deposit(Origin::signed(2), value, 12));
+        assert_eq!(
+            Kton::withdraw_deposit_early(Origin::signed(2), 2),
+            Err("too few free funds in account")
+        );
+        assert_eq!(Kton::deposit_ledger(&2).unwrap().total, value);
+        assert_eq!(locked(2), vec![value]);
+        assert_eq!(removed(), penalty);
+    });
+}
+
+#[test]
+fn deposit_appends_to_the_ledger() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        let value = 100 * COIN as u64;
+        let kton_before = Kton::free_balance(&1);
+        assert_eq!(Kton::deposit(Origin::signed(1), 0, 12), Err("deposit value must be positive"));
+        assert_eq!(Kton::deposit(Origin::signed(1), value, 37), Err("months must be between 1 and 36"));
+        assert!(Kton::deposit_ledger(&1).is_none());
+
+        assert_ok!(Kton::deposit(Origin::signed(1), value, 12));
+        Timestamp::set_timestamp(100);
+        assert_ok!(Kton::deposit(Origin::signed(1), 2 * value, 36));
+
+        let deposit = Kton::deposit_ledger(&1).unwrap();
+        assert_eq!(deposit.total, 3 * value);
+        assert_eq!(deposit.deposit_list, vec![
+            IndividualDeposit { id: 0, month: 12, start_at: 0, value },
+            IndividualDeposit { id: 1, month: 36, start_at: 100, value: 2 * value },
+        ]);
+        assert_eq!(Ring::locks(&1).iter().map(|l| l.amount).collect::<Vec<_>>(), vec![3 * value]);
+
+        let minted = <MonthlyCompoundCurve as IssuanceCurve<u64, u64>>::compute(12, value).unwrap()
+            + <MonthlyCompoundCurve as IssuanceCurve<u64, u64>>::compute(36, 2 * value).unwrap();
+        assert_eq!(Kton::free_balance(&1), kton_before + minted);
+    });
+}
+
+#[test]
+fn deposit_respects_other_locks() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        // 1000 ring, of which 700 are locked by another module
+        let value = 100 * COIN as u64;
+        Ring::set_lock(*b"otherlck", &1, 7 * value, u64::max_value(), WithdrawReasons::all());
+
+        assert_ok!(Kton::deposit(Origin::signed(1), 2 * value, 12));
+        assert_eq!(
+            Kton::deposit(Origin::signed(1), 2 * value, 12),
+            Err("account liquidity restrictions prevent withdrawal")
+        );
+        assert_eq!(
+            Kton::deposit(Origin::signed(1), 9 * value, 12),
+            Err("not enough free ring to deposit")
+        );
+
+        // a failed check leaves the locks untouched
+        let locks = Ring::locks(&1).iter().map(|l| (l.id, l.amount)).collect::<Vec<_>>();
+        assert_eq!(locks, vec![(*b"otherlck", 7 * value), (DEPOSIT_ID, 2 * value)]);
+        assert_eq!(Kton::deposit_ledger(&1).unwrap().total, 2 * value);
+
+        assert_ok!(Kton::deposit(Origin::signed(1), value, 12));
+        assert_eq!(Kton::deposit_ledger(&1).unwrap().total, 3 * value);
+    });
+}
+
+#[test]
+fn old_deposit_ledgers_are_migrated() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        use migration::deprecated::v0;
+
+        let value = 100 * COIN as u64;
+        <StorageVersion<Test>>::put(0);
+        <v0::DepositLedger<Test>>::insert(&1, v0::Deposit {
+            total: 2 * value,
+            deposit_list: vec![
+                v0::IndividualDeposit { month: 1, start_at: 0, value, claimed: false },
+                v0::IndividualDeposit { month: 12, start_at: 0, value, claimed: false },
+                v0::IndividualDeposit { month: 3, start_at: 0, value, claimed: true },
+            ],
+        });
+        Ring::set_lock(DEPOSIT_ID, &1, 2 * value, u64::max_value(), WithdrawReasons::all());
+        // the old layout does not decode as the new one
+        assert!(Kton::deposit_ledger(&1).is_none());
+
+        migration::on_runtime_upgrade::<Test>();
+        assert_eq!(<StorageVersion<Test>>::get(), migration::CURRENT_VERSION);
+
+        // the ledger is converted by the first withdrawal, the claimed deposit is dropped
+        Timestamp::set_timestamp(MONTH_IN_SECONDS as u64);
+        assert_ok!(Kton::withdraw_deposit(Origin::signed(1)));
+        let deposit = Kton::deposit_ledger(&1).unwrap();
+        assert_eq!(deposit.total, value);
+        assert_eq!(deposit.deposit_list.iter().map(|d| (d.month, d.start_at, d.value)).collect::<Vec<_>>(), vec![(12, 0, value)]);
+        assert_eq!(Ring::locks(&1).iter().map(|l| l.amount).collect::<Vec<_>>(), vec![value]);
+
+        // a new deposit is appended to a converted ledger
+        <v0::DepositLedger<Test>>::insert(&2, v0::Deposit {
+            total: value,
+            deposit_list: vec![v0::IndividualDeposit { month: 36, start_at: 0, value, claimed: false }],
+        });
+        assert_ok!(Kton::deposit(Origin::signed(2), value, 1));
+        let deposit = Kton::deposit_ledger(&2).unwrap();
+        assert_eq!(deposit.total, 2 * value);
+        assert_eq!(deposit.deposit_list.iter().map(|d| d.month).collect::<Vec<_>>(), vec![36, 1]);
+    });
+}
+
+#[test]
+fn migrated_deposits_get_unique_ids() {
+    let kton_balances = vec![(1, 1_000_000_000), (2, 1_000_000_000), (3, 1_000_000_000)];
+    with_externalities(&mut ExtBuilder::default().kton_balances(kton_balances).build(), || {
+        use migration::deprecated::v0;
+
+        let value = 100 * COIN as u64;
+        let old_deposit = |month| v0::IndividualDeposit { month, start_at: 0, value, claimed: false };
+        let ids = |who: AccountId| -> Vec<u64> {
+            Kton::deposit_ledger(&who).unwrap().deposit_list.iter().map(|d| d.id).collect()
+        };
+
+        assert_ok!(Kton::deposit(Origin::signed(3), value, 12));
+        <v0::DepositLedger<Test>>::insert(&1, v0::Deposit {
+            total: 2 * value,
+            deposit_list: vec![old_deposit(12), old_deposit(24)],
+        });
+        <v0::DepositLedger<Test>>::insert(&2, v0::Deposit { total: value, deposit_list: vec![old_deposit(6)] });
+
+        // ids are given in the order the ledgers are converted, after the ones already used
+        assert_ok!(Kton::withdraw_deposit_early(Origin::signed(1), 2));
+        assert_eq!(ids(1), vec![1]);
+        assert_ok!(Kton::deposit(Origin::signed(2), value, 1));
+        assert_eq!(ids(2), vec![3, 4]);
+        assert_eq!(ids(3), vec![0]);
+        assert_eq!(Kton::deposit_count(), 5);
+    });
+}
+
+#[test]
+fn old_rewards_are_settled_into_dividends() {
+    with_externalities(&mut ExtBuilder::default().build(), || {
+        use migration::deprecated::v0;
+
+        // 5 ring per kton before the migration
+        <StorageVersion<Test>>::put(0);
+        <v0::RewardPerShare<Test>>::put(5);
+        <v0::RewardPaidOut<Test>>::insert(&1, 200);
+        <v0::RewardPaidOut<Test>>::insert(&3, 2000);
+        <v0::RewardPaidOut<Test>>::insert(&4, -100);
+
+        migration::on_runtime_upgrade::<Test>();
+        assert_eq!(Kton::reward_per_share(), U256::from(5 * REWARD_PER_SHARE_PRECISION));
+        assert_eq!(Kton::reward_can_withdraw(&1), 300);
+        // never paid out
+        assert_eq!(Kton::reward_can_withdraw(&2), 1000);
+        // paid out more than earned
+        assert_eq!(Kton::reward_can_withdraw(&3), 0);
+        assert_eq!(Kton::reward_can_withdraw(&4), 2100);
+
+        // 1000 kton in total
+        Kton::reward_to_pot(1000);
+        assert_eq!(Kton::reward_can_withdraw(&1), 400);
+        assert_eq!(Kton::reward_can_withdraw(&3), 300);
+
+        // the old reward is settled by the first change of the dividend
+        assert_ok!(<Kton as Currency<_>>::transfer(&1, &3, 100));
+        assert!(!<v0::RewardPaidOut<Test>>::exists(&1));
+        assert!(!<v0::RewardPaidOut<Test>>::exists(&3));
+        assert_eq!(Kton::dividend(&1).unclaimed, U256::from(400 * REWARD_PER_SHARE_PRECISION));
+        assert_eq!(Kton::reward_can_withdraw(&1), 400);
+        assert_eq!(Kton::reward_can_withdraw(&3), 300);
+
+        Kton::reward_to_pot(1000);
+        assert_eq!(Kton::reward_can_withdraw(&1), 400);
+        assert_eq!(Kton::reward_can_withdraw(&3), 700);
+    });
+}
+
+#[test]
+fn emptied_reserved_balance_is_not_dust() {
+    with_externalities(&mut ExtBuilder::default().existential_deposit(10).build(), || {
+        let killed = |outcome: UpdateBalanceOutcome| if let UpdateBalanceOutcome::AccountKilled = outcome { true } else { false };
+        assert_ok!(Kton::reserve(&1, 50));
+        assert!(killed(Kton::set_reserved_balance(&1, 5)));
+        assert_eq!(removed(), 5);
+        assert!(!<ReservedBalance<Test>>::exists(&1));
+
+        assert_ok!(Kton::reserve(&1, 20));
+        assert_eq!(Kton::unreserve(&1, 20), 0);
+        assert!(!killed(Kton::set_reserved_balance(&1, 0)));
+        assert!(!<ReservedBalance<Test>>::exists(&1));
+        assert_eq!(removed(), 5);
+        assert_eq!(Kton::free_balance(&1), 50);
+        assert!(reaped().is_empty());
+    });
+}
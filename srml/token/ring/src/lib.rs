@@ -31,6 +31,7 @@ use primitives::traits::{
 	MaybeSerializeDebug, Saturating
 };
 use system::{IsDeadAccount, OnNewAccount, ensure_signed};
+use dsupport::traits::LockedBalance;
 
 mod mock;
 
@@ -910,6 +911,22 @@ where
 	}
 }
 
+impl<T: Trait<I>, I: Instance> LockedBalance<T::AccountId> for Module<T, I>
+where
+	T::Balance: MaybeSerializeDebug
+{
+	fn locked_balance(who: &T::AccountId, except: LockIdentifier, reason: WithdrawReason) -> T::Balance {
+		let vesting = match reason {
+			WithdrawReason::Reserve | WithdrawReason::Transfer => Self::vesting_balance(who),
+			_ => Zero::zero(),
+		};
+		let now = <timestamp::Module<T>>::now();
+		Self::locks(who).into_iter()
+			.filter(|l| l.id != except && now < l.until && l.reasons.contains(reason))
+			.fold(vesting, |locked, l| locked.max(l.amount))
+	}
+}
+
 impl<T: Trait<I>, I: Instance> MakePayment<T::AccountId> for Module<T, I> {
 	fn make_payment(transactor: &T::AccountId, encoded_len: usize) -> Result {
 		let encoded_len = T::Balance::from(encoded_len as u32);